@@ -0,0 +1,126 @@
+/// Words of a stem: split on separators (anything not alphanumeric) and on case changes,
+/// e.g. `myFile_HTTPServer-v2` => `my`, `File`, `HTTP`, `Server`, `v2`
+fn words(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut ys = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                ys.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|x| x.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower) {
+                ys.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        ys.push(word);
+    }
+    ys
+}
+
+/// `hello` => `Hello`, `hELLO` => `Hello`
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(c) => c
+            .to_uppercase()
+            .chain(chars.flat_map(|c| c.to_lowercase()))
+            .collect(),
+    }
+}
+
+/// `my file-name` => `My File-Name`, separators are kept
+pub(crate) fn title(s: &str) -> String {
+    let mut y = String::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            y.push_str(&capitalize(&std::mem::take(&mut word)));
+            y.push(c);
+        }
+    }
+    y.push_str(&capitalize(&word));
+    y
+}
+
+/// `My File-Name` => `my_file_name`
+pub(crate) fn snake(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `My File_Name` => `my-file-name`
+pub(crate) fn kebab(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `my file_name` => `myFileName`
+pub(crate) fn camel(s: &str) -> String {
+    words(s)
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            if i == 0 {
+                x.to_lowercase()
+            } else {
+                capitalize(x)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            words("myFile_HTTPServer-v2"),
+            ["my", "File", "HTTP", "Server", "v2"]
+        );
+        assert_eq!(words("HTTPServer_v2"), ["HTTP", "Server", "v2"]);
+        assert_eq!(words("parseURL"), ["parse", "URL"]);
+        assert_eq!(words("v2Beta"), ["v2", "Beta"]);
+        assert_eq!(words("  __ "), Vec::<String>::new());
+        assert_eq!(words("Ünïcode Wörds"), ["Ünïcode", "Wörds"]);
+    }
+
+    #[test]
+    fn cases() {
+        assert_eq!(snake("HTTPServer_v2"), "http_server_v2");
+        assert_eq!(kebab("HTTPServer_v2"), "http-server-v2");
+        assert_eq!(camel("HTTPServer_v2"), "httpServerV2");
+        assert_eq!(snake("My File-Name"), "my_file_name");
+        assert_eq!(kebab("My File_Name"), "my-file-name");
+        assert_eq!(camel("my file_name"), "myFileName");
+        assert_eq!(camel("XMLHttpRequest"), "xmlHttpRequest");
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(title("my file-name"), "My File-Name");
+        assert_eq!(title("hELLO wORLD"), "Hello World");
+        // separators are kept, acronyms are not
+        assert_eq!(title("HTTP_server  v2"), "Http_Server  V2");
+        assert_eq!(capitalize(""), "");
+    }
+}
@@ -1,17 +1,22 @@
-use crate::{Method, Target, Task};
+use crate::{CounterScope, Method, Output, Sort, Target, Task};
 
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Source: File & directory
-    pub input: String,
+    #[arg(required = true)]
+    pub input: Option<String>,
 
     /// Target: File & directory
     #[arg(short, long, value_enum, value_name("Target"))]
     pub target: Option<Target>,
 
-    /// Tasks: rename & undo with history
-    #[arg(short, long, value_enum)]
+    /// Tasks: rename, undo & redo with history
+    #[arg(long, value_enum)]
     pub task: Option<Task>,
 
     /// Methods for renaming
@@ -42,14 +47,163 @@ pub struct Cli {
     #[arg(long)]
     pub delimiter: Option<String>,
 
+    /// Regex pattern to search for, for `Method::Regex`
+    #[arg(long)]
+    pub find: Option<String>,
+
+    /// Replacement for `Method::Regex`, `$1` & `${name}` refer to capture groups
+    #[arg(long)]
+    pub replace: Option<String>,
+
+    /// Case-insensitive matching, for `Method::Regex`
+    #[arg(long)]
+    pub ignore_case: bool,
+
+    /// Replace the first match only, for `Method::Regex`
+    #[arg(long)]
+    pub replace_first: bool,
+
+    /// Match against the full file name instead of the file stem, for `Method::Regex`
+    #[arg(long)]
+    pub full_name: bool,
+
+    /// Keep entries not matched as they are instead of failing, for `Method::Regex`
+    #[arg(long)]
+    pub skip_unmatched: bool,
+
+    /// Naming template, implies `--method template`, e.g. `{parent}_{n:04}_{stem|lower}.{ext}`
+    /// Placeholders: {stem} {ext} {name} {parent} {n} {n:04} {random} {random:8} {uuid} {date} {date:%Y%m%d} {mtime}
+    /// Filters: {stem|lower} {stem|upper} {stem|title} {stem|snake} {stem|kebab} {stem|camel}
+    #[arg(long, verbatim_doc_comment)]
+    pub template: Option<String>,
+
+    /// Only rename entries matching this glob, on their name or path relative to the source.
+    /// Repeatable, e.g. `--include '*.jpg' --include 'raw/**'`
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip entries matching this glob, folders matched are not descended into. Repeatable
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Like `--include`, with a regex
+    #[arg(long, value_name = "REGEX")]
+    pub include_regex: Vec<String>,
+
+    /// Like `--exclude`, with a regex
+    #[arg(long, value_name = "REGEX")]
+    pub exclude_regex: Vec<String>,
+
+    /// Only rename entries with these extensions, e.g. `--ext jpg,png`
+    #[arg(long, value_delimiter = ',')]
+    pub ext: Vec<String>,
+
+    /// Only rename files at least this large, e.g. `10K`, `1.5M`, `2G`
+    #[arg(long, value_name = "SIZE")]
+    pub min_size: Option<String>,
+
+    /// Only rename files at most this large, e.g. `10K`, `1.5M`, `2G`
+    #[arg(long, value_name = "SIZE")]
+    pub max_size: Option<String>,
+
+    /// Only rename entries modified after a date, or within a duration, e.g. `2024-01-31`, `7d`, `12h`
+    #[arg(long, value_name = "DATE|DURATION")]
+    pub newer_than: Option<String>,
+
+    /// Only rename entries modified before a date, or longer ago than a duration, e.g. `30d`
+    #[arg(long, value_name = "DATE|DURATION")]
+    pub older_than: Option<String>,
+
+    /// Only rename empty files, or empty folders with `--target dir`
+    #[arg(long)]
+    pub empty: bool,
+
     /// Not preserving consistent file stems
     /// e.g. Files with the same filestem in the same folder should remain consistent after renaming
     #[arg(long)]
     pub indiscriminate: bool,
 
-    // #[arg(short, long)]
-    // pub hidden_included: bool,
+    /// Where the rename history is kept, the user's data directory by default
+    #[arg(long, env = "RENIFY_HISTORY_DIR", value_name = "DIR")]
+    pub history_dir: Option<std::path::PathBuf>,
+
+    /// Print the full rename plan without touching the filesystem
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Include hidden entries: dot-files, dot-folders & those marked hidden by the system.
+    /// The history is never renamed
+    #[arg(long)]
+    pub hidden: bool,
+
+    /// Skip entries ignored by `.gitignore`, `.ignore` & `.renifyignore` files, and `.git`
+    #[arg(long)]
+    pub respect_ignore: bool,
+
+    /// Point links under the source at the new names of their destinations, see `--target symlink`
+    #[arg(long)]
+    pub retarget: bool,
+
+    /// Order entries of a folder are numbered in. If not set, `natural` for `num` & `znum`,
+    /// names that are numbers first for others
+    #[arg(long, value_enum)]
+    pub sort: Option<Sort>,
+
+    /// Compare names case-insensitively when sorting, e.g. `IMG1` < `img2` < `Img10`
+    #[arg(long)]
+    pub sort_ignore_case: bool,
+
+    /// Reverse that order
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Entries sharing a counter, for `num`, `znum` & `{n}`: `global` numbers a recursive
+    /// run as one sequence. [default: per-dir]
+    #[arg(long, value_enum)]
+    pub counter_scope: Option<CounterScope>,
+
     /// Execute without asking
     #[arg(short, long)]
     pub yes: bool,
+
+    /// Output format: `json` prints the options, the plan & the outcome of each file
+    /// instead of logs & progress bars
+    #[arg(long, value_enum, default_value_t = Output::Text, global = true)]
+    pub output: Output,
+
+    /// Never prompt: options not given take their defaults, or fail if required.
+    /// Implied when stdin is not a terminal
+    #[arg(long)]
+    pub no_interactive: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Browse and undo the rename history
+    #[command(subcommand)]
+    History(HistoryCommand),
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// List sessions, newest first
+    List,
+
+    /// Print the old → new mapping of a session
+    Show {
+        /// Session id, or a unique prefix of it
+        id: String,
+    },
+
+    /// Undo exactly one session, if no later session touched the same paths
+    Undo {
+        /// Session id, or a unique prefix of it
+        id: String,
+    },
+
+    /// Redo exactly one undone session, if its files are still in place
+    Redo {
+        /// Session id, or a unique prefix of it
+        id: String,
+    },
 }
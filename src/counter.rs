@@ -0,0 +1,34 @@
+use std::path::{Path, PathBuf};
+
+/// Entries sharing a counter, for `Method::Num`, `Method::Znum` & `{n}`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CounterScope {
+    /// One sequence across the whole tree, folder after folder, parents first
+    Global,
+    /// One sequence per folder
+    #[default]
+    PerDir,
+    /// One sequence per depth
+    PerDepth,
+}
+
+impl CounterScope {
+    /// Name used by `--counter-scope`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::PerDir => "per-dir",
+            Self::PerDepth => "per-depth",
+        }
+    }
+
+    /// Key of the counter of entries in the folder `pd` at `depth`
+    pub(crate) fn key(&self, pd: &Path, depth: usize) -> (usize, PathBuf) {
+        match self {
+            Self::Global => (0, PathBuf::new()),
+            Self::PerDir => (0, pd.to_path_buf()),
+            Self::PerDepth => (depth, PathBuf::new()),
+        }
+    }
+}
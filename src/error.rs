@@ -0,0 +1,71 @@
+/// Failures told apart by their exit code, carried through `anyhow::Error`
+#[derive(Debug)]
+pub enum Error {
+    /// Source missing, options or template not valid
+    BadInput(String),
+
+    /// Nothing to rename, undo or redo
+    NothingMatched(String),
+
+    /// Cancelled at a prompt, or interrupted by a signal
+    Cancelled(String),
+
+    /// A name already taken, files not in place, or a later session in the way
+    Conflict(String),
+
+    /// History unreadable or unwritable
+    Io(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::BadInput(_) => 2,
+            Self::NothingMatched(_) => 3,
+            Self::Cancelled(_) => 4,
+            Self::Conflict(_) => 5,
+            Self::Io(_) => 6,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::BadInput(x)
+            | Self::NothingMatched(x)
+            | Self::Cancelled(x)
+            | Self::Conflict(x)
+            | Self::Io(x) => f.write_str(x),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Exit code of `err`: the one of the first `Error` in its chain, `4` for an interrupted
+/// prompt, `6` for other I/O errors, `1` otherwise.
+///
+/// | Code | Meaning |
+/// | ---- | ------- |
+/// | 0 | Success |
+/// | 1 | Other failure |
+/// | 2 | Bad input |
+/// | 3 | Nothing matched |
+/// | 4 | Cancelled or interrupted |
+/// | 5 | Conflict |
+/// | 6 | I/O failure |
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    for x in err.chain() {
+        if let Some(x) = x.downcast_ref::<Error>() {
+            return x.exit_code();
+        }
+        if let Some(x) = x.downcast_ref::<std::io::Error>() {
+            return match x.kind() {
+                std::io::ErrorKind::Interrupted => 4,
+                _ => 6,
+            };
+        }
+    }
+    1
+}
@@ -0,0 +1,369 @@
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::history::LEGACY_DIR;
+use crate::{Error, History, Renamer};
+
+/// `--include`, `--exclude` & `--ext` compiled, matched against the name of an entry
+/// and its path relative to the source, and metadata filters parsed
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    include_regex: Vec<Regex>,
+    exclude_regex: Vec<Regex>,
+
+    /// Lowercase, without the dot
+    ext: HashSet<String>,
+
+    /// In bytes, for files
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+
+    /// Bounds of the modification time
+    newer_than: Option<SystemTime>,
+    older_than: Option<SystemTime>,
+
+    /// Empty files or empty folders only
+    empty: bool,
+
+    /// History directory, canonicalized, never renamed nor walked into
+    history: Option<PathBuf>,
+
+    /// Ignore files honored, with `--respect-ignore`
+    ignores: Option<Ignores>,
+}
+
+impl Filter {
+    pub(crate) fn new(renamer: &Renamer) -> Result<Self> {
+        Ok(Self {
+            include: Self::globs(&renamer.include)?,
+            exclude: Self::globs(&renamer.exclude)?,
+            include_regex: Self::regexes(&renamer.include_regex)?,
+            exclude_regex: Self::regexes(&renamer.exclude_regex)?,
+            ext: renamer
+                .ext
+                .iter()
+                .map(|x| x.trim().trim_start_matches('.').to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect(),
+            min_size: renamer.min_size.as_deref().map(Self::size).transpose()?,
+            max_size: renamer.max_size.as_deref().map(Self::size).transpose()?,
+            newer_than: renamer.newer_than.as_deref().map(Self::time).transpose()?,
+            older_than: renamer.older_than.as_deref().map(Self::time).transpose()?,
+            empty: renamer.empty,
+            history: History::new(renamer.history_dir.clone())
+                .ok()
+                .and_then(|x| x.dir().canonicalize().ok()),
+            ignores: renamer.respect_ignore.then(Ignores::default),
+        })
+    }
+
+    /// Ignored by an ignore file, along with everything below it for a directory
+    pub(crate) fn ignored(&self, p: &Path, is_dir: bool) -> bool {
+        self.ignores.as_ref().is_some_and(|x| x.ignored(p, is_dir))
+    }
+
+    /// History kept by renify, here or in the legacy `.renify-cache`
+    pub(crate) fn is_history(&self, p: &Path) -> bool {
+        self.history.as_ref().is_some_and(|x| p.starts_with(x))
+            || p.file_name() == Some(LEGACY_DIR.as_ref())
+    }
+
+    /// A folder the history is kept in, which renaming would move it
+    pub(crate) fn holds_history(&self, p: &Path) -> bool {
+        self.history.as_ref().is_some_and(|x| x.starts_with(p))
+    }
+
+    /// Picked as a target by its metadata: size, modification time & emptiness.
+    /// Entries whose metadata can not be read are left out.
+    pub(crate) fn kept(&self, p: &Path) -> bool {
+        let metadata = match p.symlink_metadata() {
+            Err(_) => return false,
+            Ok(x) => x,
+        };
+        if metadata.is_file() {
+            let size = metadata.len();
+            if self.min_size.is_some_and(|x| size < x)
+                || self.max_size.is_some_and(|x| size > x)
+                || (self.empty && size > 0)
+            {
+                return false;
+            }
+        } else if metadata.is_dir()
+            && self.empty
+            && !p.read_dir().is_ok_and(|mut xs| xs.next().is_none())
+        {
+            return false;
+        }
+        if self.newer_than.is_some() || self.older_than.is_some() {
+            let t = match metadata.modified() {
+                Err(_) => return false,
+                Ok(t) => t,
+            };
+            if self.newer_than.is_some_and(|x| t <= x) || self.older_than.is_some_and(|x| t >= x) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// `1024`, `10K`, `1.5MB`, `2GiB`, ... in bytes, units are powers of 1024
+    fn size(s: &str) -> Result<u64> {
+        let err = || Error::BadInput(format!("Invalid size: {s}, e.g. 100, 10K, 1.5M, 2G"));
+        let x = s.trim().to_uppercase();
+        let x = x.strip_suffix("IB").or(x.strip_suffix('B')).unwrap_or(&x);
+        let (n, unit) = match x.find(|c: char| c.is_ascii_alphabetic()) {
+            None => (x, 0),
+            Some(i) => (
+                &x[..i],
+                match &x[i..] {
+                    "K" => 1,
+                    "M" => 2,
+                    "G" => 3,
+                    "T" => 4,
+                    _ => anyhow::bail!(err()),
+                },
+            ),
+        };
+        let n: f64 = n.trim().parse().map_err(|_| err())?;
+        if n < 0. {
+            anyhow::bail!(err());
+        }
+        Ok((n * 1024f64.powi(unit)) as u64)
+    }
+
+    /// A point in time: a duration back from now, e.g. `30m`, `12h`, `7d`, `2w`, `1d12h`,
+    /// or a date, e.g. `2024-01-31`, `2024-01-31 08:00`, `2024-01-31T08:00:00+02:00`
+    fn time(s: &str) -> Result<SystemTime> {
+        let s = s.trim();
+        if let Some(d) = Self::duration(s) {
+            return SystemTime::now()
+                .checked_sub(d)
+                .ok_or_else(|| Error::BadInput(format!("Duration too long: {s}")).into());
+        }
+        if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) {
+            return Ok(t.into());
+        }
+        let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|f| chrono::NaiveDateTime::parse_from_str(s, f).ok())
+            .or_else(|| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|x| x.and_hms_opt(0, 0, 0))
+            });
+        match naive.and_then(|x| x.and_local_timezone(chrono::Local).earliest()) {
+            Some(t) => Ok(t.into()),
+            None => anyhow::bail!(Error::BadInput(format!(
+                "Invalid date or duration: {s}, e.g. 7d, 12h, 2024-01-31"
+            ))),
+        }
+    }
+
+    /// `90s`, `30m`, `12h`, `7d`, `2w`, or several of them, e.g. `1d12h`
+    fn duration(s: &str) -> Option<Duration> {
+        let mut secs = 0u64;
+        let mut n = String::new();
+        for c in s.chars() {
+            if c.is_ascii_digit() {
+                n.push(c);
+                continue;
+            }
+            let unit = match c {
+                's' => 1,
+                'm' => 60,
+                'h' => 3600,
+                'd' => 86400,
+                'w' => 604800,
+                _ => return None,
+            };
+            secs = secs.checked_add(n.parse::<u64>().ok()?.checked_mul(unit)?)?;
+            n.clear();
+        }
+        match n.is_empty() && !s.is_empty() {
+            true => Some(Duration::from_secs(secs)),
+            false => None,
+        }
+    }
+
+    /// Left out, along with everything below it for a directory
+    pub(crate) fn excluded(&self, rel: &Path) -> bool {
+        self.exclude
+            .as_ref()
+            .is_some_and(|x| Self::is_match(rel, |p| x.is_match(p)))
+            || self
+                .exclude_regex
+                .iter()
+                .any(|x| Self::is_match(rel, |p| x.is_match(&p.to_string_lossy())))
+    }
+
+    /// Picked as a target: matching one of the includes, if any, and one of the extensions, if any
+    pub(crate) fn included(&self, rel: &Path) -> bool {
+        let includes = self.include.is_some() || !self.include_regex.is_empty();
+        let included = !includes
+            || self
+                .include
+                .as_ref()
+                .is_some_and(|x| Self::is_match(rel, |p| x.is_match(p)))
+            || self
+                .include_regex
+                .iter()
+                .any(|x| Self::is_match(rel, |p| x.is_match(&p.to_string_lossy())));
+        included
+            && (self.ext.is_empty()
+                || rel
+                    .extension()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| self.ext.contains(&x.to_lowercase())))
+    }
+
+    /// Whether `f` matches the name of `rel`, or `rel` itself
+    fn is_match<F: Fn(&Path) -> bool>(rel: &Path, f: F) -> bool {
+        rel.file_name().is_some_and(|x| f(Path::new(x))) || f(rel)
+    }
+
+    fn globs(xs: &[String]) -> Result<Option<GlobSet>> {
+        if xs.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for x in xs.iter() {
+            let glob = GlobBuilder::new(x)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| Error::BadInput(format!("Invalid glob {x}: {err}")))?;
+            builder.add(glob);
+        }
+        Ok(Some(builder.build().map_err(|err| {
+            Error::BadInput(format!("Invalid globs: {err}"))
+        })?))
+    }
+
+    fn regexes(xs: &[String]) -> Result<Vec<Regex>> {
+        xs.iter()
+            .map(|x| {
+                Regex::new(x)
+                    .map_err(|err| Error::BadInput(format!("Invalid regex {x}: {err}")).into())
+            })
+            .collect()
+    }
+}
+
+/// Ignore files of each folder, read once: `.gitignore`, `.ignore` & `.renifyignore`,
+/// the latter taking precedence. Those of the folders above are honored too, up to the
+/// root of the git repository, if any.
+#[derive(Debug, Default)]
+struct Ignores {
+    /// Folder => its rules, if any, & whether it is the root of a git repository
+    cache: RefCell<HashMap<PathBuf, (Option<Gitignore>, bool)>>,
+}
+
+impl Ignores {
+    const FILES: [&'static str; 3] = [".gitignore", ".ignore", ".renifyignore"];
+
+    fn ignored(&self, p: &Path, is_dir: bool) -> bool {
+        if p.file_name() == Some(".git".as_ref()) {
+            return true;
+        }
+
+        // deepest rules first
+        for d in p.ancestors().skip(1) {
+            let mut cache = self.cache.borrow_mut();
+            let (rules, root) = cache
+                .entry(d.to_path_buf())
+                .or_insert_with(|| Self::read(d));
+            if let Some(rules) = rules {
+                let m = rules.matched(p, is_dir);
+                if m.is_ignore() {
+                    return true;
+                }
+                if m.is_whitelist() {
+                    return false;
+                }
+            }
+            if *root {
+                break;
+            }
+        }
+        false
+    }
+
+    fn read(d: &Path) -> (Option<Gitignore>, bool) {
+        let mut builder = GitignoreBuilder::new(d);
+        let mut found = false;
+        for x in Self::FILES.iter() {
+            let p = d.join(x);
+            if p.is_file() {
+                // lines not valid are skipped, as git does
+                let _ = builder.add(p);
+                found = true;
+            }
+        }
+        let rules = match found {
+            false => None,
+            true => builder.build().ok(),
+        };
+        (rules, d.join(".git").exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size() {
+        assert_eq!(Filter::size("100").unwrap(), 100);
+        assert_eq!(Filter::size("10K").unwrap(), 10 * 1024);
+        assert_eq!(Filter::size("1.5M").unwrap(), 1536 * 1024);
+        assert_eq!(Filter::size("2GiB").unwrap(), 2 << 30);
+        assert_eq!(Filter::size(" 3 kb ").unwrap(), 3 * 1024);
+        assert_eq!(Filter::size("1T").unwrap(), 1 << 40);
+        assert!(Filter::size("-1K").is_err());
+        assert!(Filter::size("1X").is_err());
+        assert!(Filter::size("K").is_err());
+        assert!(Filter::size("").is_err());
+    }
+
+    #[test]
+    fn duration() {
+        let d = |s| Filter::duration(s).map(|x| x.as_secs());
+        assert_eq!(d("90s"), Some(90));
+        assert_eq!(d("30m"), Some(1800));
+        assert_eq!(d("1d12h"), Some(36 * 3600));
+        assert_eq!(d("2w"), Some(14 * 86400));
+        // a unit is needed, bare numbers are ambiguous
+        assert_eq!(d("30"), None);
+        assert_eq!(d("1d30"), None);
+        assert_eq!(d("-1d"), None);
+        assert_eq!(d("d"), None);
+        assert_eq!(d(""), None);
+    }
+
+    #[test]
+    fn time() {
+        let t = Filter::time("1d12h").unwrap();
+        let ago = SystemTime::now().duration_since(t).unwrap().as_secs();
+        assert!((36 * 3600..36 * 3600 + 60).contains(&ago));
+
+        let day = Filter::time("2024-01-31").unwrap();
+        let minute = Filter::time("2024-01-31 08:00").unwrap();
+        assert_eq!(minute.duration_since(day).unwrap().as_secs(), 8 * 3600);
+        assert_eq!(Filter::time("2024-01-31T08:00:00").unwrap(), minute);
+        let utc = Filter::time("2024-01-31T08:00:00+00:00").unwrap();
+        let offset = Filter::time("2024-01-31T10:00:00+02:00").unwrap();
+        assert_eq!(utc, offset);
+
+        assert!(Filter::time("30").is_err());
+        assert!(Filter::time("-7d").is_err());
+        assert!(Filter::time("31/01/2024").is_err());
+        assert!(Filter::time("2024-02-30").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::{Entry, Error, Journal, RenameOp};
+
+/// Environment variable overriding the history directory
+pub const HISTORY_DIR_ENV: &str = "RENIFY_HISTORY_DIR";
+
+/// Where caches were kept before: `.renify-cache` in the current directory
+pub(crate) const LEGACY_DIR: &str = ".renify-cache";
+
+/// A recorded run
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Timestamp the journal is named after
+    pub id: String,
+
+    /// Journal file
+    pub path: PathBuf,
+
+    /// Source renamed, unknown for legacy caches
+    pub root: Option<PathBuf>,
+
+    /// RFC 3339, empty for legacy caches
+    pub created: String,
+
+    /// Method used, unknown for legacy caches
+    pub method: Option<String>,
+}
+
+/// Whether the renames of a session are in effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// All of them
+    Applied,
+    /// None of them, it was undone
+    Reverted,
+    /// Some of them
+    Partial,
+}
+
+impl std::fmt::Display for SessionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Applied => "applied",
+            Self::Reverted => "reverted",
+            Self::Partial => "partial",
+        })
+    }
+}
+
+impl SessionState {
+    /// State of a session made of `entries`
+    pub fn of(entries: &[Entry]) -> Self {
+        let n = entries.iter().filter(|x| x.applied).count();
+        match n {
+            0 if !entries.is_empty() => Self::Reverted,
+            n if n == entries.len() => Self::Applied,
+            _ => Self::Partial,
+        }
+    }
+}
+
+impl Session {
+    /// Renames recorded, with their state
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        Journal::entries(&self.path)
+    }
+
+    pub fn state(&self) -> Result<SessionState> {
+        Ok(SessionState::of(&self.entries()?))
+    }
+}
+
+/// Rename history, one journal per run, kept under the user's data directory
+/// and indexed by the source root: `<dir>/<root key>/<timestamp>.jsonl`
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    /// `dir`, or the default one
+    pub fn new(dir: Option<PathBuf>) -> Result<Self> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => Self::default_dir()?,
+        };
+        Ok(Self { dir })
+    }
+
+    /// `<data dir>/renify/history`, e.g. `~/.local/share/renify/history` on Linux
+    pub fn default_dir() -> Result<PathBuf> {
+        match dirs::data_dir() {
+            None => anyhow::bail!(Error::Io(format!("Can not locate the data directory of current user, please set `--history-dir` or `{}`.",
+                HISTORY_DIR_ENV))),
+            Some(d) => Ok(d.join("renify").join("history")),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Start recording a new run on `root`
+    pub fn create(&self, root: &Path, method: &str) -> Result<(Session, Journal)> {
+        let d = self.dir.join(Self::root_key(root));
+        std::fs::create_dir_all(&d)?;
+        let id = chrono::Local::now().format("%Y%m%d%H%M%S%f").to_string();
+        let path = d.join(format!("{id}.jsonl"));
+        let journal = Journal::create(&path, root, method)?;
+        let header = Journal::read_header(&path)?;
+        Ok((
+            Session {
+                id,
+                path,
+                root: Some(root.to_path_buf()),
+                created: header.created,
+                method: header.method,
+            },
+            journal,
+        ))
+    }
+
+    /// All sessions, newest first. Legacy caches in the current directory are included.
+    pub fn sessions(&self) -> Result<Vec<Session>> {
+        let mut ys = Vec::new();
+        if self.dir.is_dir() {
+            for d in self.dir.read_dir()?.flatten() {
+                if d.path().is_dir() {
+                    ys.extend(Self::scan(&d.path(), true)?);
+                }
+            }
+        }
+        if let Ok(d) = std::env::current_dir() {
+            let d = d.join(LEGACY_DIR);
+            if d.is_dir() {
+                ys.extend(Self::scan(&d, false)?);
+            }
+        }
+        ys.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(ys)
+    }
+
+    /// Sessions recorded on `root` or anywhere below it, newest first
+    pub fn sessions_under(&self, root: &Path) -> Result<Vec<Session>> {
+        Ok(self
+            .sessions()?
+            .into_iter()
+            .filter(|x| x.root.as_ref().is_some_and(|x| x.starts_with(root)))
+            .collect())
+    }
+
+    /// The session whose id is or starts with `id`
+    pub fn find(&self, id: &str) -> Result<Session> {
+        let mut ys: Vec<Session> = self
+            .sessions()?
+            .into_iter()
+            .filter(|x| x.id.starts_with(id))
+            .collect();
+        match ys.len() {
+            0 => anyhow::bail!(Error::NothingMatched(format!("No session found: {id}"))),
+            1 => Ok(ys.remove(0)),
+            n => anyhow::bail!(Error::BadInput(format!(
+                "Ambiguous session id: {id} matches {n} sessions"
+            ))),
+        }
+    }
+
+    /// A later session, still in effect, touching the same paths as `session`
+    /// (or paths inside them), if any
+    pub fn conflict(&self, session: &Session) -> Result<Option<(Session, PathBuf)>> {
+        let paths: HashSet<PathBuf> = Journal::read(&session.path)?
+            .into_iter()
+            .flat_map(|x| [x.from, x.to])
+            .collect();
+        for later in self.sessions()?.into_iter().filter(|x| x.id > session.id) {
+            let xs: HashSet<PathBuf> = later
+                .entries()?
+                .into_iter()
+                .filter(|x| x.applied)
+                .flat_map(|x| [x.op.from, x.op.to])
+                .collect();
+            let hit = xs
+                .iter()
+                .find(|p| p.ancestors().any(|x| paths.contains(x)))
+                .or_else(|| paths.iter().find(|p| p.ancestors().any(|x| xs.contains(x))))
+                .cloned();
+            if let Some(p) = hit {
+                return Ok(Some((later, p)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Move a legacy cache into the history, so that undo & redo can be recorded in it.
+    /// It is rooted at the current directory, which relative paths are resolved against.
+    pub fn import(&self, session: &Session) -> Result<Session> {
+        if session.root.is_some() {
+            return Ok(session.clone());
+        }
+        let cwd = std::env::current_dir()?;
+        let d = self.dir.join(Self::root_key(&cwd));
+        std::fs::create_dir_all(&d)?;
+        let path = d.join(format!("{}.jsonl", session.id));
+        let journal = Journal::create(&path, &cwd, "legacy")?;
+        for x in Journal::read(&session.path)? {
+            journal.record(&RenameOp {
+                from: cwd.join(x.from),
+                to: cwd.join(x.to),
+            })?;
+        }
+        std::fs::remove_file(&session.path)?;
+        let header = Journal::read_header(&path)?;
+        Ok(Session {
+            id: session.id.clone(),
+            path,
+            root: header.root,
+            created: header.created,
+            method: header.method,
+        })
+    }
+
+    /// Forget a session, and its folder once empty
+    pub fn remove(&self, session: &Session) -> Result<()> {
+        std::fs::remove_file(&session.path)?;
+        if let Some(d) = session.path.parent() {
+            let _ = std::fs::remove_dir(d);
+        }
+        Ok(())
+    }
+
+    fn scan(d: &Path, jsonl: bool) -> Result<Vec<Session>> {
+        let mut ys = Vec::new();
+        for entry in d.read_dir()?.flatten() {
+            let path = entry.path();
+            if !path.is_file() || (jsonl && path.extension() != Some("jsonl".as_ref())) {
+                continue;
+            }
+            let id = match path.file_stem().and_then(|x| x.to_str()) {
+                None => continue,
+                Some(x) => x.to_string(),
+            };
+            let header = Journal::read_header(&path)?;
+            ys.push(Session {
+                id,
+                path,
+                root: header.root,
+                created: header.created,
+                method: header.method,
+            });
+        }
+        Ok(ys)
+    }
+
+    /// Folder name of a source root: FNV-1a of its path
+    fn root_key(root: &Path) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in root.as_os_str().as_encoded_bytes() {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+}
@@ -1,58 +1,83 @@
 use anyhow::Result;
 use dialoguer::theme::ColorfulTheme;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use std::collections::{BTreeMap, HashMap};
-use std::io::Write;
 use std::path::{Path, PathBuf};
-use uuid::Uuid;
-use walkdir::{DirEntry, WalkDir};
 
+use crate::journal::SYNC_EVERY;
 use crate::{
-    build_progressbar, Cli, Method, Target, Task, BIT_MAX, CHECK_MARK, CROSS_MARK, INVALID_CHARS,
+    build_progressbar, Cli, Command, Error, History, HistoryCommand, Journal, Method, Output,
+    Pending, RenameOp, RenamePlan, Renamer, Report, Session, SessionState, Target, Task, Template,
+    BIT_MAX, CHECK_MARK, CROSS_MARK, INVALID_CHARS,
 };
+use std::cell::Cell;
 
 impl Cli {
     pub fn run(&mut self) -> Result<()> {
         let theme = Self::build_theme();
 
+        // interrupted sessions first, unless nothing is to be changed
+        let browsing = matches!(
+            self.command,
+            Some(Command::History(
+                HistoryCommand::List | HistoryCommand::Show { .. }
+            ))
+        );
+        if !self.dry_run && !browsing {
+            self.recover(&theme)?;
+        }
+
+        // Subcommands
+        if let Some(Command::History(command)) = &self.command {
+            return self.history(command);
+        }
+
         // Task
         self.ask_task(&theme)?;
         match &self.task {
-            None => anyhow::bail!("{CROSS_MARK} No task specified"),
+            None => anyhow::bail!(Error::BadInput("No task specified".into())),
             Some(task) => match task {
                 Task::Rename => self.rename(&theme)?,
                 Task::Undo => self.undo(&theme)?,
+                Task::Redo => self.redo(&theme)?,
             },
         }
         Ok(())
     }
 
     pub fn rename(&mut self, theme: &ColorfulTheme) -> Result<()> {
+        if self.template.is_some() && self.method.is_none() {
+            self.method = Some(Method::Template);
+        }
+        if !self.interactive() {
+            let ys = self.missing_flags();
+            if !ys.is_empty() {
+                anyhow::bail!(Error::BadInput(format!(
+                    "Missing in non-interactive mode: {}{}",
+                    ys.join(", "),
+                    match &self.method {
+                        None => String::new(),
+                        Some(method) => format!(" (for --method {})", method.name()),
+                    }
+                )));
+            }
+        }
+
         // source & target
         let source_type = self.check_source()?;
         self.ask_target(source_type, theme)?;
-        let ys = self.fetch_targets(&self.input)?;
-
-        // cache
-        let mut dir_cache = match std::env::current_dir() {
-            Err(err) => anyhow::bail!("Cache folder get current_dir: {err}."),
-            Ok(mut d) => {
-                d.push(".renify-cache");
-                if !d.exists() {
-                    std::fs::create_dir_all(&d).expect("Cache folder failed to create: {err}.");
-                }
-                d
-            }
-        };
+        let mut ys = self.renamer().fetch_targets(self.input())?;
 
         // continue?
         if ys.is_empty() {
-            self.status_log(
-                false,
-                "Found",
-                &format!("{:?} x0", self.target.unwrap()),
-                "Not Found",
-            );
+            let filters = self.filters();
+            anyhow::bail!(Error::NothingMatched(format!(
+                "Nothing to rename: {:?} x0 found in {}{}",
+                self.renamer().target,
+                self.input(),
+                match filters.is_empty() {
+                    true => String::new(),
+                    false => format!(" with {}", filters.join(" ")),
+                }
+            )));
         } else {
             let ntotal = ys
                 .values()
@@ -60,21 +85,34 @@ impl Cli {
             self.status_log(
                 true,
                 "Found",
-                &format!("{:?} x{}", self.target.unwrap(), ntotal),
-                "",
+                &format!("{:?} x{}", self.renamer().target, ntotal),
+                &self.filters().join(" "),
             );
+            if let Some(sort) = self.sort {
+                self.status_log(
+                    true,
+                    "Order",
+                    &format!("{:?}{}", sort, if self.reverse { ", reversed" } else { "" }),
+                    &format!(
+                        "--sort {}{}",
+                        sort.name(),
+                        if self.reverse { " --reverse" } else { "" }
+                    ),
+                );
+            }
 
             // Method
             self.ask_method(theme)?;
 
             // Question asking
             match &self.method {
-                None => anyhow::bail!("{CROSS_MARK} No task specified"),
+                None => anyhow::bail!(Error::BadInput("No method specified".into())),
                 Some(method) => match method {
                     Method::Random => self.ask_nbit(theme, ntotal)?,
                     Method::Znum => {
                         self.ask_start_from(theme)?;
-                        self.ask_nbit(theme, ntotal + self.start.unwrap())?;
+                        // wide enough for the global total, whatever the counter scope
+                        self.ask_nbit(theme, ntotal + self.renamer().start)?;
                     }
                     Method::Num => self.ask_start_from(theme)?,
                     Method::Time => self.ask_delimiter(theme)?,
@@ -82,11 +120,81 @@ impl Cli {
                         self.ask_delimiter(theme)?;
                         self.ask_with(theme)?;
                     }
+                    Method::Regex => {
+                        self.ask_find(theme)?;
+                        self.ask_replace(theme)?;
+                    }
+                    Method::Template => {
+                        self.ask_template(theme)?;
+                        if let Some(template) = &self.template {
+                            if Template::parse(template)?.uses_counter() {
+                                self.ask_start_from(theme)?;
+                            }
+                        }
+                    }
                     _ => {}
                 },
             }
 
+            // order, which may depend on the method
+            self.renamer().order(&mut ys);
+
+            // plan, & links moved along
+            let mut plan = self.renamer().plan_targets(&ys)?;
+            let relinks = self.renamer().relinks(self.input(), &plan)?;
+            let mut dangling = Vec::new();
+            match self.retarget {
+                true => plan.relinks = relinks,
+                false => dangling = relinks.into_iter().map(|x| x.link).collect(),
+            }
+            let mut report = Report::new("rename", &plan);
+            report.dangling = dangling.clone();
+            report.source = Some(self.source_root()?);
+            report.options = Some(self.renamer());
+            report.dry_run = self.dry_run;
+            if self.output == Output::Text {
+                for x in dangling.iter() {
+                    self.status_log(false, "Dangling", &x.display().to_string(), "");
+                }
+                if !dangling.is_empty() {
+                    self.status_log(
+                        false,
+                        "Links left dangling",
+                        &format!("x{}", dangling.len()),
+                        "--retarget to point them at the new names",
+                    );
+                }
+            }
+            if self.dry_run {
+                match self.output {
+                    Output::Json => report.print()?,
+                    Output::Text => self.print_plan(&plan),
+                }
+                self.status_log(
+                    true,
+                    "Dry run",
+                    &format!("{} x{}", "To be renamed", plan.len()),
+                    "Nothing has been changed",
+                );
+                return Ok(());
+            }
+
+            // names already as planned, nothing to record
+            if plan.is_empty() {
+                if self.output == Output::Json {
+                    report.print()?;
+                }
+                self.status_log(
+                    true,
+                    "Nothing to change",
+                    &format!("{:?} x{}", self.renamer().target, ntotal),
+                    "Names are already as planned",
+                );
+                return Ok(());
+            }
+
             if !self.yes
+                && self.interactive()
                 && !dialoguer::Confirm::with_theme(theme)
                     .with_prompt("Ready to go")
                     .default(true)
@@ -94,415 +202,681 @@ impl Cli {
                     .wait_for_newline(true)
                     .interact()?
             {
-                self.status_log(false, "Task cancelled.", "", "");
-            }
-            let pb = build_progressbar(ntotal as u64, " Renaming");
-            let mut map_pf_stem = HashMap::<PathBuf, String>::new();
-            let mut map_pd_cnt: HashMap<PathBuf, usize> = HashMap::new();
-
-            // cache file
-            dir_cache.push(chrono::Local::now().format("%Y%m%d%H%M%S%f").to_string());
-            let mut f_cache = std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(dir_cache)?;
-
-            // loop
-            for (_, paths) in ys.iter().rev() {
-                for (pd, pfs) in paths.iter() {
-                    for pf in pfs.iter() {
-                        pb.inc(1);
-                        let path_new = self.gen_uniq(pf, pd, &mut map_pd_cnt, &mut map_pf_stem)?;
-                        self.rename_and_cache(pf, &path_new, &mut f_cache)?;
+                anyhow::bail!(Error::Cancelled("Task cancelled.".into()));
+            }
+
+            // history
+            let history = History::new(self.history_dir.clone())?;
+            let (session, journal) =
+                history.create(&self.source_root()?, self.renamer().method.name())?;
+
+            // loop, all or nothing, declared beforehand
+            let steps = plan.steps();
+            journal.intend(&steps)?;
+            let pb = self.progressbar(steps.len(), " Renaming");
+            let (applied, reached) = (Cell::new(0), Cell::new(0));
+            let result = RenamePlan::apply_steps(
+                &steps,
+                |i, _| {
+                    journal.apply(i)?;
+                    applied.set(i + 1);
+                    reached.set(i + 1);
+                    pb.inc(1);
+                    Ok(())
+                },
+                |i, _| {
+                    applied.set(i);
+                    pb.set_position(i as u64);
+                    journal.revert(i)
+                },
+            );
+            journal.commit()?;
+
+            // links, once everything is renamed
+            let relinked = match &result {
+                Ok(n) if *n == steps.len() => plan.relinks.iter().try_for_each(|x| {
+                    journal.relink(x)?;
+                    x.apply().map_err(|err| {
+                        err.context(format!("Failed to retarget {}", x.link.display()))
+                    })
+                }),
+                _ => Ok(()),
+            };
+            if self.output == Output::Json {
+                // fully rolled back sessions are forgotten right below
+                if session.state()? != SessionState::Reverted {
+                    report.session = Some(session.id.clone());
+                }
+                report.settle(&steps, applied.get(), reached.get(), &result);
+                if let Err(err) = &relinked {
+                    report.error = Some(format!("{:#}", err));
+                }
+                report.print()?;
+            }
+            relinked?;
+            match result {
+                Ok(n) if n == steps.len() => pb.finish(),
+                _ => {
+                    pb.abandon();
+                    // nothing left on disk, nothing to remember
+                    if session.state()? == SessionState::Reverted {
+                        history.remove(&session)?;
                     }
+                    let n = result?;
+                    anyhow::bail!(Error::Cancelled(format!(
+                        "Interrupted: {} of {} renamed. {}",
+                        RenamePlan::compose(&steps[..n]).len(),
+                        plan.len(),
+                        Self::hint_interrupted(&session, n)
+                    )));
                 }
             }
-            pb.finish();
         }
         Ok(())
     }
 
-    fn undo(&self, theme: &ColorfulTheme) -> Result<()> {
-        match std::env::current_dir() {
-            Err(err) => anyhow::bail!("Cache folder get current_dir: {err}."),
-            Ok(mut d) => {
-                d.push(".renify-cache");
-                if !d.exists() {
-                    self.status_log(
-                        false,
-                        "Failed to undo",
-                        "No caches found",
-                        "Please run renify beforehand",
-                    );
-                } else {
-                    // glob
-                    let mut ys = Vec::new();
-                    for entry in d.read_dir().expect("read_dir call failed").flatten() {
-                        ys.push(entry.path().to_str().unwrap().to_string());
-                    }
+    /// Options resolved so far, with defaults for those not asked yet
+    fn renamer(&self) -> Renamer {
+        let default = Renamer::default();
+        Renamer {
+            target: self.target.unwrap_or(default.target),
+            method: self.method.clone().unwrap_or(default.method),
+            recursive: self.recursive.unwrap_or(default.recursive),
+            depth: self.depth,
+            nbits: self.nbits.unwrap_or(default.nbits),
+            start: self.start.unwrap_or(default.start),
+            with: self.with.clone(),
+            delimiter: self.delimiter.clone().unwrap_or(default.delimiter),
+            indiscriminate: self.indiscriminate,
+            find: self.find.clone(),
+            replace: self.replace.clone().unwrap_or(default.replace),
+            ignore_case: self.ignore_case,
+            replace_first: self.replace_first,
+            full_name: self.full_name,
+            skip_unmatched: self.skip_unmatched,
+            template: self.template.clone(),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            include_regex: self.include_regex.clone(),
+            exclude_regex: self.exclude_regex.clone(),
+            ext: self.ext.clone(),
+            min_size: self.min_size.clone(),
+            max_size: self.max_size.clone(),
+            newer_than: self.newer_than.clone(),
+            older_than: self.older_than.clone(),
+            empty: self.empty,
+            hidden: self.hidden,
+            history_dir: self.history_dir.clone(),
+            respect_ignore: self.respect_ignore,
+            retarget: self.retarget,
+            sort: self.sort,
+            sort_ignore_case: self.sort_ignore_case,
+            reverse: self.reverse,
+            counter_scope: self.counter_scope.unwrap_or_default(),
+        }
+    }
 
-                    if ys.is_empty() {
-                        self.status_log(
-                            false,
-                            "Failed to undo",
-                            "No caches found",
-                            "Please run renify beforehand",
-                        );
-                    }
+    fn print_plan(&self, plan: &RenamePlan) {
+        let nw = plan.len().to_string().len();
+        let width = plan
+            .iter()
+            .map(|op| op.from.display().to_string().chars().count())
+            .max()
+            .unwrap_or(0);
+        for x in plan.relinks.iter() {
+            println!(
+                "{}  {}{}{}",
+                console::Style::new()
+                    .white()
+                    .dim()
+                    .apply_to(format!("{:>nw$}", "↪")),
+                console::Style::new()
+                    .white()
+                    .apply_to(format!("{:<width$}", x.link.display().to_string())),
+                console::Style::new().bold().white().dim().apply_to(" ⇢ "),
+                console::Style::new()
+                    .color256(49)
+                    .bright()
+                    .apply_to(x.to.display()),
+            );
+        }
+        for (i, RenameOp { from: p0, to: p1 }) in plan.iter().enumerate() {
+            println!(
+                "{}  {}{}{}",
+                console::Style::new()
+                    .white()
+                    .dim()
+                    .apply_to(format!("{:>nw$}", i + 1)),
+                console::Style::new()
+                    .white()
+                    .apply_to(format!("{:<width$}", p0.display().to_string())),
+                console::Style::new().bold().white().dim().apply_to(" → "),
+                console::Style::new()
+                    .color256(49)
+                    .bright()
+                    .apply_to(p1.display()),
+            );
+        }
+    }
 
-                    // sort
-                    ys.sort_by(|a, b| {
-                        std::fs::metadata(a)
-                            .unwrap()
-                            .modified()
-                            .unwrap()
-                            .partial_cmp(&std::fs::metadata(b).unwrap().modified().unwrap())
-                            .unwrap()
-                            .reverse()
-                    });
-
-                    // asking
-                    let i = dialoguer::Select::with_theme(theme)
-                        .with_prompt("Cache")
-                        .default(0)
-                        .items(&ys[..])
-                        .interact()?;
+    fn undo(&self, theme: &ColorfulTheme) -> Result<()> {
+        let history = History::new(self.history_dir.clone())?;
+        let ys = self.ask_sessions(&history, SessionState::Reverted, "undo", theme)?;
 
-                    // undo
-                    let yys: Vec<_> = ys.drain(i + 1..).collect();
-                    for y in ys.iter() {
-                        let contents = match std::fs::read_to_string(y) {
-                            Ok(s) => s,
-                            Err(err) => {
-                                anyhow::bail!("Error when read_to_string: {err}")
-                            }
-                        };
-                        let pb = build_progressbar(contents.lines().count() as u64, " Undoing");
-                        for line in contents.lines().rev() {
-                            pb.inc(1);
-                            let v: Vec<&str> = line.split(' ').collect();
-                            match std::fs::rename(v[1], v[0]) {
-                                Ok(_) => {}
-                                Err(err) => {
-                                    anyhow::bail!("Error when renaming: {err}")
-                                }
-                            };
-                        }
-                        match std::fs::remove_file(y) {
-                            Ok(_) => {}
-                            Err(err) => {
-                                anyhow::bail!("Error when removing file: {err}")
-                            }
-                        }
-                        pb.finish();
-                    }
+        // newer ones first
+        for y in ys.iter() {
+            self.undo_session(&history, y)?;
+        }
+        Ok(())
+    }
 
-                    // cleanup
-                    if yys.is_empty() {
-                        match std::fs::remove_dir(d) {
-                            Ok(_) => {}
-                            Err(err) => {
-                                anyhow::bail!("Error when removing directory: {err}")
-                            }
-                        }
-                    }
-                }
-            }
-        };
+    fn redo(&self, theme: &ColorfulTheme) -> Result<()> {
+        let history = History::new(self.history_dir.clone())?;
+        let ys = self.ask_sessions(&history, SessionState::Applied, "redo", theme)?;
 
+        // older ones first
+        for y in ys.iter().rev() {
+            self.redo_session(&history, y)?;
+        }
         Ok(())
     }
 
-    fn fetch_targets<P: AsRef<Path>>(
+    /// Sessions of this source not in `skipped` state, newest first, or those of all folders
+    /// to pick from when it has none.
+    /// Undo goes from the newest one down to the one picked, redo from the oldest one up to it.
+    fn ask_sessions(
         &self,
-        source: P,
-    ) -> Result<BTreeMap<usize, BTreeMap<PathBuf, Vec<PathBuf>>>> {
-        let source = source.as_ref().canonicalize().unwrap();
-        let mut ys = if source.is_file() {
-            let mut ys = BTreeMap::new();
-            let mut y = BTreeMap::new();
-            y.insert(
-                source
-                    .parent()
-                    .expect("You can not reach the parent of root directory.")
-                    .to_path_buf(),
-                vec![source.to_path_buf()],
-            );
-            ys.insert(0_usize, y);
-            ys
-        } else {
-            let mut ys: BTreeMap<usize, BTreeMap<PathBuf, Vec<PathBuf>>> = BTreeMap::new();
-            for entry in WalkDir::new(source)
-                .follow_links(matches!(self.target.unwrap(), Target::Symlink))
-                .into_iter()
-                .filter_entry(|x| !self._is_hidden(x))
-            {
-                match entry {
-                    Ok(entry) => {
-                        // skip root dir
-                        if entry.file_type().is_dir() && entry.depth() == 0 {
-                            continue;
-                        }
+        history: &History,
+        skipped: SessionState,
+        task: &str,
+        theme: &ColorfulTheme,
+    ) -> Result<Vec<Session>> {
+        let mut ys = match self.source_root() {
+            Ok(root) => history.sessions_under(&root)?,
+            Err(_) => Vec::new(),
+        };
 
-                        // hidden excluded
-                        // if !self.hidden_included && self._is_hidden(&entry) {
-                        //     continue;
-                        // }
+        // sessions of other folders, to pick from only
+        let others = ys.is_empty();
+        if others {
+            if !self.interactive() {
+                anyhow::bail!(Error::NothingMatched(format!(
+                    "Failed to {task}: no history found for {}. Sessions of other folders are listed by `renify history list`, use `renify history {task} <id>`",
+                    self.input()
+                )));
+            }
+            ys = history.sessions()?;
+        }
+        let mut xs = Vec::new();
+        for y in ys.into_iter() {
+            if y.state()? != skipped {
+                xs.push(y);
+            }
+        }
+        let ys = xs;
+        if ys.is_empty() {
+            anyhow::bail!(Error::NothingMatched(format!(
+                "Failed to {task}: no history found. {}",
+                match skipped {
+                    SessionState::Reverted => "Please run renify beforehand.",
+                    _ => "Nothing has been undone.",
+                }
+            )));
+        }
 
-                        // non-recrusive
-                        if !self.recursive.unwrap() && entry.depth() > 1 {
-                            continue;
-                        }
+        // the latest one, without prompts, confirmed beforehand as renaming is
+        if !self.interactive() {
+            let mut missing = Vec::new();
+            if skipped != SessionState::Reverted {
+                missing.push("the session to redo, use `renify history redo <id>`");
+            }
+            if !self.yes {
+                missing.push("--yes");
+            }
+            if !missing.is_empty() {
+                anyhow::bail!(Error::BadInput(format!(
+                    "Missing in non-interactive mode: {}",
+                    missing.join(", ")
+                )));
+            }
+            return Ok(ys[..1].to_vec());
+        }
 
-                        // depth
-                        if let Some(d) = self.depth {
-                            if entry.depth() > d {
-                                continue;
-                            }
-                        }
+        // asking
+        let items: Vec<String> = ys
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} · {}",
+                    if x.created.is_empty() {
+                        &x.id
+                    } else {
+                        &x.created
+                    },
+                    x.root.as_ref().map_or(x.path.display(), |x| x.display())
+                )
+            })
+            .collect();
+        let i = dialoguer::Select::with_theme(theme)
+            .with_prompt(match others {
+                true => "History of other folders",
+                false => "History",
+            })
+            .default(0)
+            .items(&items[..])
+            .interact()?;
+        let picked = match skipped {
+            SessionState::Reverted => &ys[..=i],
+            _ => &ys[i..],
+        };
 
-                        // symlinks are not included!
-                        // TODO: files or dirs that links(soft & hard) points to are not included!
-                        if entry.path_is_symlink() {
-                            continue;
-                        }
+        // down to the one picked, of its folder only
+        Ok(picked
+            .iter()
+            .filter(|x| !others || x.root == ys[i].root)
+            .cloned()
+            .collect())
+    }
 
-                        // filter
-                        if entry.file_type().is_file() {
-                            if let Target::File = self.target.unwrap() {
-                            } else {
-                                continue;
-                            }
-                        } else if entry.file_type().is_dir() {
-                            if let Target::Dir = self.target.unwrap() {
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            continue;
-                        }
+    /// Revert renames of `session` still in effect, marking them in its journal
+    fn undo_session(&self, history: &History, session: &Session) -> Result<()> {
+        let session = history.import(session)?;
+        let journal = Journal::open(&session.path)?;
+        let entries = session.entries()?;
+        let seqs: Vec<usize> = (0..entries.len())
+            .rev()
+            .filter(|&i| entries[i].applied)
+            .collect();
 
-                        // save
-                        ys.entry(entry.depth())
-                            .or_default()
-                            .entry(
-                                entry
-                                    .path()
-                                    .parent()
-                                    .expect("You can not reach the parent of root directory.")
-                                    .to_path_buf(),
-                            )
-                            .or_default()
-                            .push(entry.path().to_path_buf());
-                    }
-                    Err(e) => {
-                        println!("walkdir error: {:?} (Basically won't happen)", e);
-                    }
-                }
+        // links first, while they are where they were retargeted
+        let relinks = Journal::relinks(&session.path)?;
+        for x in relinks.iter().rev() {
+            x.revert()?;
+        }
+        let result = self.replay(&session, &journal, true, &seqs, " Undoing");
+        if result.is_err() {
+            for x in relinks.iter() {
+                let _ = x.apply();
             }
-            ys
-        };
+        }
+        result
+    }
+
+    /// Reapply renames of `session` undone before, once their files are checked to be in place
+    fn redo_session(&self, history: &History, session: &Session) -> Result<()> {
+        let session = history.import(session)?;
+        let journal = Journal::open(&session.path)?;
+        let entries = session.entries()?;
+        let seqs: Vec<usize> = (0..entries.len())
+            .filter(|&i| !entries[i].applied)
+            .collect();
+        self.replay(&session, &journal, false, &seqs, " Redoing")?;
+        for x in Journal::relinks(&session.path)?.iter() {
+            x.apply()?;
+        }
+        Ok(())
+    }
 
-        // re-order
-        for (_, paths) in ys.iter_mut() {
-            for (_, path) in paths.iter_mut() {
-                path.sort_by(|a, b| {
-                    a.file_stem()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .parse::<usize>()
-                        .unwrap_or(usize::MAX)
-                        .cmp(
-                            &b.file_stem()
-                                .unwrap()
-                                .to_str()
-                                .unwrap()
-                                .parse::<usize>()
-                                .unwrap_or(usize::MAX),
-                        )
-                });
-            }
-        }
-        Ok(ys)
-    }
-
-    pub fn gen_uniq(
+    /// Undo, or redo, renames `seqs` of `session` in this order as one batch, marked in `journal`
+    fn replay(
         &self,
-        pf: &Path,
-        pd: &Path,
-        map_pd_cnt: &mut HashMap<PathBuf, usize>,
-        map_pf_stem: &mut HashMap<PathBuf, String>,
-    ) -> Result<PathBuf> {
-        // Generate unique file stem
-        if !self.indiscriminate {
-            if let Target::File = self.target.unwrap() {
-                let path_wo_ext = pf.with_extension("");
-                if !map_pf_stem.is_empty() && map_pf_stem.contains_key(&path_wo_ext) {
-                    let _stem = &map_pf_stem[&path_wo_ext];
-                    let mut path_new = pf.with_file_name(_stem);
-                    if let Some(suffix) = pf.extension() {
-                        path_new = path_new.with_extension(suffix);
-                    }
-                    return Ok(path_new);
-                }
+        session: &Session,
+        journal: &Journal,
+        undo: bool,
+        seqs: &[usize],
+        message: &str,
+    ) -> Result<()> {
+        let pending = Pending {
+            undo,
+            seqs: seqs.to_vec(),
+        };
+        let steps = pending.steps(&session.entries()?, seqs);
+        let mut report = Report::new(
+            if undo { "undo" } else { "redo" },
+            &RenamePlan::compose(&steps),
+        );
+        report.source = session.root.clone();
+        report.session = Some(session.id.clone());
+        if let Err(err) = RenamePlan::check_steps(&steps) {
+            if self.output == Output::Json {
+                report.error = Some(format!("{:#}", err));
+                report.print()?;
             }
+            return Err(err);
         }
+        journal.begin(undo, seqs)?;
+        let mark = |journal: &Journal, seq: usize, undo: bool| match undo {
+            true => journal.revert(seq),
+            false => journal.apply(seq),
+        };
+        let pb = self.progressbar(steps.len(), message);
+        let (applied, reached) = (Cell::new(0), Cell::new(0));
+        let result = RenamePlan::apply_steps(
+            &steps,
+            |i, _| {
+                mark(journal, seqs[i], undo)?;
+                applied.set(i + 1);
+                reached.set(i + 1);
+                pb.inc(1);
+                Ok(())
+            },
+            |i, _| {
+                applied.set(i);
+                pb.set_position(i as u64);
+                mark(journal, seqs[i], !undo)
+            },
+        );
+        journal.commit()?;
+        if self.output == Output::Json {
+            report.settle(&steps, applied.get(), reached.get(), &result);
+            report.print()?;
+        }
+        match result {
+            Ok(n) if n == steps.len() => pb.finish(),
+            _ => {
+                pb.abandon();
+                let n = result?;
+                anyhow::bail!(Error::Cancelled(format!(
+                    "Interrupted: {} of {} {}. {}",
+                    RenamePlan::compose(&steps[..n]).len(),
+                    RenamePlan::compose(&steps).len(),
+                    if undo { "undone" } else { "redone" },
+                    Self::hint_interrupted(session, n)
+                )));
+            }
+        }
+        Ok(())
+    }
 
-        loop {
-            let stem = match &self.method {
-                None => anyhow::bail!("{CROSS_MARK} No task specified"),
-                Some(method) => match method {
-                    Method::Time => chrono::Local::now()
-                        .format(&format!(
-                            "%Y{}%m{}%d{}%H{}%M{}%S{}%f",
-                            self.delimiter.as_ref().unwrap(),
-                            self.delimiter.as_ref().unwrap(),
-                            self.delimiter.as_ref().unwrap(),
-                            self.delimiter.as_ref().unwrap(),
-                            self.delimiter.as_ref().unwrap(),
-                            self.delimiter.as_ref().unwrap()
-                        ))
-                        .to_string(),
-                    Method::Uuid => Uuid::new_v4().to_string(),
-                    Method::Prefix => {
-                        assert!(
-                            self.with.is_some(),
-                            "> You should set the prefix content by `--with` when using `Method::Prefix`."
-                        );
-                        let stem = pf.file_stem().unwrap().to_str().unwrap();
-                        format!(
-                            "{}{}{}",
-                            self.with.clone().unwrap(),
-                            self.delimiter.as_ref().unwrap(),
-                            stem
-                        )
-                    }
-                    Method::Append => {
-                        assert!(
-                            self.with.is_some(),
-                            "> You should set the postfix content by `--with` when using `Method::Append`."
-                        );
-                        let stem = pf.file_stem().unwrap().to_str().unwrap();
-                        format!(
-                            "{}{}{}",
-                            stem,
-                            self.delimiter.as_ref().unwrap(),
-                            self.with.clone().unwrap()
-                        )
-                    }
-                    Method::Num => {
-                        let count = map_pd_cnt
-                            .entry(pd.to_path_buf())
-                            .or_insert(self.start.unwrap() - 1);
-                        *count += 1;
-                        count.to_string()
-                    }
-                    Method::Znum => {
-                        let count = map_pd_cnt
-                            .entry(pd.to_path_buf())
-                            .or_insert(self.start.unwrap() - 1);
-                        *count += 1;
-                        format!("{:0>1$}", count, self.nbits.unwrap())
-                    }
-                    Method::Random => thread_rng()
-                        .sample_iter(&Alphanumeric)
-                        .take(self.nbits.unwrap())
-                        .map(char::from)
-                        .collect(),
-                    // Method::Lowercase => {
-                    //     let stem = pf.file_stem().unwrap().to_str().unwrap().to_lowercase();
-                    //     stem
-                    // }
-                    // Method::Uppercase => {
-                    //     let stem = pf.file_stem().unwrap().to_str().unwrap().to_uppercase();
-                    //     stem
-                    // }
-                    // _ => todo!(),
-                },
-            };
-
-            // check if new stem file exists
-            let mut p_new = pf.with_file_name(stem);
+    /// How to go on with a session interrupted after `n` renames
+    fn hint_interrupted(session: &Session, n: usize) -> String {
+        match n {
+            0 => "Nothing has been changed.".to_string(),
+            _ => format!(
+                "Go on with `renify history redo {id}`, or roll back with `renify history undo {id}`.",
+                id = session.id
+            ),
+        }
+    }
 
-            // extend with suffix
-            if let Some(suffix) = pf.extension() {
-                p_new = p_new.with_extension(suffix);
+    /// Look for sessions interrupted, e.g. by a crash, and offer to complete or roll them back
+    fn recover(&self, theme: &ColorfulTheme) -> Result<()> {
+        let history = History::new(self.history_dir.clone())?;
+        for session in history.sessions()?.iter() {
+            // journals only, legacy caches have no operation left open
+            if session.root.is_none() || session.path.extension() != Some("jsonl".as_ref()) {
+                continue;
             }
 
-            // save if keep consistance
-            if !self.indiscriminate {
-                if let Target::File = self.target.unwrap() {
-                    let _stem = p_new.file_stem().unwrap().to_str().unwrap().to_string();
-                    let path_wo_ext = pf.with_extension("");
-                    map_pf_stem.insert(path_wo_ext, _stem);
+            // committed, read no further; or held by a renify still running
+            let journal = match Journal::is_closed(&session.path).and_then(|closed| match closed {
+                true => Ok(None),
+                false => Journal::try_open(&session.path),
+            }) {
+                Err(err) => {
+                    self.status_log(false, "Unreadable", &session.id, &format!("{:#}", err));
+                    continue;
+                }
+                Ok(None) => continue,
+                Ok(Some(x)) => x,
+            };
+            let (entries, pending) = match Journal::load(&session.path) {
+                Err(err) => {
+                    self.status_log(false, "Unreadable", &session.id, &format!("{:#}", err));
+                    continue;
+                }
+                Ok((_, None)) => continue,
+                Ok((entries, Some(x))) => (entries, x),
+            };
+
+            // marks lost with the crash: renames found on disk are done
+            let left = pending.left(&entries);
+            let n = RenamePlan::applied_prefix(&pending.steps(&entries, &left), SYNC_EVERY + 1);
+            for &seq in left[..n].iter() {
+                match pending.undo {
+                    true => journal.revert(seq)?,
+                    false => journal.apply(seq)?,
                 }
             }
+            let entries = session.entries()?;
+            let left = pending.left(&entries);
+            if left.is_empty() {
+                journal.commit()?;
+                continue;
+            }
 
-            if !p_new.exists() {
-                break Ok(p_new);
-            } else {
-                match &self.method {
-                    None => anyhow::bail!("{CROSS_MARK} No task specified"),
-                    Some(method) => match method {
-                        // Method::Uppercase | Method::Lowercase | Method::Num | Method::Znum => {
-                        Method::Num | Method::Znum => {
-                            break Ok(pf.to_path_buf());
-                        }
-                        _ => {}
-                    },
+            // asking
+            self.status_log(
+                true,
+                "Interrupted",
+                &format!(
+                    "{} · {} x{} of {} left",
+                    session.id,
+                    if pending.undo { "undo" } else { "rename" },
+                    left.len(),
+                    pending.seqs.len()
+                ),
+                &session
+                    .root
+                    .as_ref()
+                    .map_or(String::new(), |x| x.display().to_string()),
+            );
+            if !self.interactive() {
+                self.status_log(
+                    true,
+                    "Left as is",
+                    &session.id,
+                    "Run renify in a terminal to complete or roll it back",
+                );
+                continue;
+            }
+            let selections = &["Complete it", "Roll it back", "Leave it for now"];
+            let i = dialoguer::Select::with_theme(theme)
+                .with_prompt("Recovery")
+                .default(0)
+                .items(&selections[..])
+                .interact()?;
+            match i {
+                0 => self.replay(session, &journal, pending.undo, &left, " Completing")?,
+                1 => {
+                    let mut done = pending.done(&entries);
+                    done.reverse();
+                    self.replay(session, &journal, !pending.undo, &done, " Rolling back")?;
                 }
+                _ => {}
             }
         }
+        Ok(())
     }
 
-    fn _is_hidden(&self, entry: &DirEntry) -> bool {
-        entry
-            .file_name()
-            .to_str()
-            .map(|p| p.starts_with('.'))
-            .unwrap_or(false)
-    }
-
-    fn max_depth<P: AsRef<Path>>(&self, source: P) -> usize {
-        let source = source.as_ref();
-        let mut depth = 0usize;
-        for entry in WalkDir::new(source).into_iter() {
-            match entry {
-                Ok(entry) => {
-                    if entry.file_type().is_dir() {
-                        if let Target::Dir = self.target.unwrap() {
-                            depth = entry.depth().max(depth);
-                        } else {
-                            continue;
-                        }
-                    } else if entry.file_type().is_file() {
-                        if let Target::File = self.target.unwrap() {
-                            depth = entry.depth().max(depth);
-                        } else {
-                            continue;
+    fn history(&self, command: &HistoryCommand) -> Result<()> {
+        let history = History::new(self.history_dir.clone())?;
+        match command {
+            HistoryCommand::List => {
+                let ys = history.sessions()?;
+                if ys.is_empty() {
+                    self.status_log(
+                        false,
+                        "History",
+                        "No history found",
+                        &history.dir().display().to_string(),
+                    );
+                }
+                for y in ys.iter() {
+                    let (n, state) = match Journal::load(&y.path) {
+                        Err(_) => (0, "unreadable".to_string()),
+                        Ok((entries, pending)) => {
+                            let steps: Vec<RenameOp> =
+                                entries.iter().map(|x| x.op.clone()).collect();
+                            (
+                                RenamePlan::compose(&steps).len(),
+                                match pending {
+                                    Some(_) => "interrupted".to_string(),
+                                    None => SessionState::of(&entries).to_string(),
+                                },
+                            )
                         }
+                    };
+                    if self.output == Output::Json {
+                        println!(
+                            "{}",
+                            serde_json::json!({
+                                "session": y.id,
+                                "created": y.created,
+                                "method": y.method,
+                                "count": n,
+                                "state": state,
+                                "source": y.root,
+                            })
+                        );
+                        continue;
                     }
+                    println!(
+                        "{}  {}  {}  {}  {:<11}  {}",
+                        console::Style::new().color256(49).bright().apply_to(&y.id),
+                        console::Style::new().white().dim().apply_to(&y.created),
+                        console::Style::new()
+                            .white()
+                            .bold()
+                            .apply_to(y.method.as_deref().unwrap_or("-")),
+                        console::Style::new().white().apply_to(format!("x{}", n)),
+                        console::Style::new().white().dim().apply_to(state),
+                        y.root.as_ref().map_or(y.path.display(), |x| x.display()),
+                    );
+                }
+            }
+            HistoryCommand::Show { id } => {
+                let y = history.find(id)?;
+                let mut plan = RenamePlan::compose(&Journal::read(&y.path)?);
+                plan.relinks = Journal::relinks(&y.path)?;
+                if self.output == Output::Json {
+                    let files: Vec<_> = plan
+                        .iter()
+                        .map(|x| serde_json::json!({ "from": x.from, "to": x.to }))
+                        .collect();
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "session": y.id,
+                            "created": y.created,
+                            "method": y.method,
+                            "state": y.state()?.to_string(),
+                            "source": y.root,
+                            "files": files,
+                            "relinks": plan.relinks,
+                        })
+                    );
+                    return Ok(());
+                }
+                self.print_plan(&plan);
+                self.status_log(
+                    true,
+                    "Session",
+                    &y.id,
+                    &format!(
+                        "{} · x{} · {}",
+                        y.method.as_deref().unwrap_or("-"),
+                        plan.len(),
+                        y.state()?
+                    ),
+                );
+            }
+            HistoryCommand::Undo { id } => {
+                let y = history.find(id)?;
+                if let Some((later, p)) = history.conflict(&y)? {
+                    anyhow::bail!(Error::Conflict(format!(
+                        "{} is touched by a later session {}. Undo {} first, or use --task undo.",
+                        p.display(),
+                        later.id,
+                        later.id
+                    )));
                 }
-                Err(_) => continue,
+                if y.state()? == SessionState::Reverted {
+                    anyhow::bail!(Error::NothingMatched(format!(
+                        "{} is already undone.",
+                        y.id
+                    )));
+                }
+                self.undo_session(&history, &y)?;
+                self.status_log(true, "Undone", &y.id, "");
+            }
+            HistoryCommand::Redo { id } => {
+                let y = history.find(id)?;
+                if y.state()? == SessionState::Applied {
+                    anyhow::bail!(Error::NothingMatched(format!(
+                        "Nothing of {} has been undone.",
+                        y.id
+                    )));
+                }
+                if let Some((later, p)) = history.conflict(&y)? {
+                    anyhow::bail!(Error::Conflict(format!(
+                        "{} is touched by a later session {}. Undo {} first.",
+                        p.display(),
+                        later.id,
+                        later.id
+                    )));
+                }
+                self.redo_session(&history, &y)?;
+                self.status_log(true, "Redone", &y.id, "");
             }
         }
-        depth
+        Ok(())
     }
 
-    fn rename_and_cache<P: AsRef<Path>>(&self, p0: P, p1: P, f: &mut std::fs::File) -> Result<()> {
-        let p0 = p0.as_ref().canonicalize()?;
-        std::fs::rename(&p0, &p1)?;
-        let p1 = p1.as_ref().canonicalize()?;
-        let _map = format!("{} {}\n", p0.display(), p1.display());
-        f.write_all(_map.as_bytes())?;
-        Ok(())
+    /// Whether prompts can be shown: not disabled, and stdin & stderr are terminals
+    fn interactive(&self) -> bool {
+        use std::io::IsTerminal;
+        !self.no_interactive && std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+    }
+
+    /// Options required, but not given, for running without prompts
+    fn missing_flags(&self) -> Vec<&'static str> {
+        let mut ys = Vec::new();
+        match &self.method {
+            None => ys.push("--method"),
+            Some(Method::Prefix | Method::Append) if self.with.is_none() => ys.push("--with"),
+            Some(Method::Regex) if self.find.is_none() => ys.push("--find"),
+            Some(Method::Template) if self.template.is_none() => ys.push("--template"),
+            _ => {}
+        }
+        if !self.yes && !self.dry_run {
+            ys.push("--yes");
+        }
+        ys
+    }
+
+    fn input(&self) -> &str {
+        self.input.as_deref().unwrap_or_default()
+    }
+
+    /// The folder renamed: the source itself, or its parent for a file
+    fn source_root(&self) -> Result<PathBuf> {
+        let p = crate::canonicalize_nofollow(Path::new(self.input()))?;
+        if p.is_dir() && !p.is_symlink() {
+            return Ok(p);
+        }
+        match p.parent() {
+            None => anyhow::bail!(Error::BadInput(
+                "You can not reach the parent of root directory.".into()
+            )),
+            Some(d) => Ok(d.to_path_buf()),
+        }
+    }
+
+    /// Progress bar, hidden with `--output json`
+    fn progressbar(&self, n: usize, prefix: &str) -> indicatif::ProgressBar {
+        let pb = build_progressbar(n as u64, prefix);
+        if self.output == Output::Json {
+            pb.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+        }
+        pb
     }
 
     #[allow(clippy::println_empty_string)]
     fn status_log(&self, status: bool, t1: &str, t2: &str, prompt: &str) {
+        if self.output == Output::Json {
+            return;
+        }
         if status {
             print!(
                 "{}",
@@ -550,16 +924,15 @@ impl Cli {
             );
         }
         println!("");
-
-        if !status {
-            std::process::exit(0);
-        }
     }
 
     fn check_source(&self) -> Result<Target> {
-        let p = std::path::Path::new(&self.input);
-        if !p.exists() {
-            self.status_log(false, "Source", " Not Exist", &self.input);
+        let p = std::path::Path::new(self.input());
+        if !crate::exists_nofollow(p) {
+            anyhow::bail!(Error::BadInput(format!(
+                "Source not exist: {}",
+                self.input()
+            )));
         }
         // check type
         let type_ = if p.is_symlink() {
@@ -569,17 +942,13 @@ impl Cli {
         } else {
             Target::Dir
         };
-        let p = p.canonicalize()?;
-        let p = p.to_str().unwrap();
+        let p = crate::canonicalize_nofollow(p)?.display().to_string();
         self.status_log(
             true,
             "Source",
+            &p,
             match type_ {
-                Target::Dir | Target::File => p,
-                Target::Symlink => &self.input,
-            },
-            match type_ {
-                Target::Dir => "Folders",
+                Target::Dir | Target::All => "Folders",
                 Target::File => "Files",
                 Target::Symlink => "Symlinks",
             },
@@ -587,16 +956,53 @@ impl Cli {
         Ok(type_)
     }
 
+    /// Filters given, as flags
+    fn filters(&self) -> Vec<String> {
+        let mut xs = Vec::new();
+        for (flag, ys) in [
+            ("--include", &self.include),
+            ("--exclude", &self.exclude),
+            ("--include-regex", &self.include_regex),
+            ("--exclude-regex", &self.exclude_regex),
+        ] {
+            xs.extend(ys.iter().map(|y| format!("{flag} {y}")));
+        }
+        if !self.ext.is_empty() {
+            xs.push(format!("--ext {}", self.ext.join(",")));
+        }
+        for (flag, y) in [
+            ("--min-size", &self.min_size),
+            ("--max-size", &self.max_size),
+            ("--newer-than", &self.newer_than),
+            ("--older-than", &self.older_than),
+        ] {
+            if let Some(y) = y {
+                xs.push(format!("{flag} {y}"));
+            }
+        }
+        if self.empty {
+            xs.push("--empty".to_string());
+        }
+        if self.hidden {
+            xs.push("--hidden".to_string());
+        }
+        if self.respect_ignore {
+            xs.push("--respect-ignore".to_string());
+        }
+        xs
+    }
+
     fn ask_task(&mut self, theme: &ColorfulTheme) -> Result<()> {
         match &self.task {
+            None if !self.interactive() => self.task = Some(Task::Rename),
             None => {
-                let selections = &["Rename", "Undo with history"];
+                let selections = &["Rename", "Undo with history", "Redo with history"];
                 let i = dialoguer::Select::with_theme(theme)
                     .with_prompt("Task")
                     .default(0)
                     .items(&selections[..])
                     .interact()?;
-                self.task = Some(Task::from(selections[i]));
+                self.task = Some(Task::try_from(selections[i])?);
             }
             Some(task) => {
                 self.status_log(
@@ -608,6 +1014,7 @@ impl Cli {
                         match task {
                             Task::Rename => "Rename",
                             Task::Undo => "Undo",
+                            Task::Redo => "Redo",
                         }
                     ),
                 );
@@ -619,22 +1026,24 @@ impl Cli {
     fn ask_target(&mut self, source_type: Target, theme: &ColorfulTheme) -> Result<()> {
         match source_type {
             Target::Symlink => {
-                self.status_log(false, "Source Error", "Not Supported", "Symlink");
+                self.target = Some(Target::Symlink);
+                self.status_log(true, "Target", "Symlink", "--target symlink");
             }
             Target::File => {
                 self.target = Some(Target::File);
                 self.status_log(true, "Target", "File", "--target file");
             }
-            Target::Dir => match self.target {
+            Target::Dir | Target::All => match self.target {
+                None if !self.interactive() => self.target = Some(Renamer::default().target),
                 None => {
-                    let selections = ["File", "Folder"];
+                    let selections = ["File", "Folder", "Symlink", "All"];
                     let i = dialoguer::Select::with_theme(theme)
                         .with_prompt("Target")
                         .default(0)
-                        .max_length(3)
+                        .max_length(4)
                         .items(&selections[..])
                         .interact()?;
-                    self.target = Some(Target::from(selections[i]));
+                    self.target = Some(Target::try_from(selections[i])?);
                 }
                 Some(target) => {
                     self.status_log(
@@ -646,7 +1055,8 @@ impl Cli {
                             match target {
                                 Target::Dir => "dir",
                                 Target::File => "file",
-                                _ => "",
+                                Target::Symlink => "symlink",
+                                Target::All => "all",
                             }
                         ),
                     );
@@ -660,7 +1070,7 @@ impl Cli {
         }
 
         // file stem consistent
-        if let Target::File = self.target.unwrap() {
+        if let Target::File | Target::All = self.renamer().target {
             self.status_log(
                 true,
                 "Preserve name consistent",
@@ -674,6 +1084,11 @@ impl Cli {
 
     fn ask_method(&mut self, theme: &ColorfulTheme) -> Result<()> {
         match &self.method {
+            None if !self.interactive() => {
+                anyhow::bail!(Error::BadInput(
+                    "Missing in non-interactive mode: --method".into()
+                ))
+            }
             None => {
                 let selections = &[
                     "Random",
@@ -683,6 +1098,14 @@ impl Cli {
                     "ZeroNumbered",
                     "Prefix",
                     "Append",
+                    "Uppercase",
+                    "Lowercase",
+                    "Title",
+                    "SnakeCase",
+                    "KebabCase",
+                    "CamelCase",
+                    "Regex",
+                    "Template",
                 ];
                 let i = dialoguer::Select::with_theme(theme)
                     .with_prompt("Method")
@@ -690,25 +1113,14 @@ impl Cli {
                     // .max_length(10)
                     .items(selections)
                     .interact()?;
-                self.method = Some(Method::from(selections[i]));
+                self.method = Some(Method::try_from(selections[i])?);
             }
             Some(method) => {
                 self.status_log(
                     true,
                     "Method",
                     &format!("{:?}", method),
-                    &format!(
-                        "--method {}",
-                        match method {
-                            Method::Random => "random",
-                            Method::Time => "time",
-                            Method::Num => "num",
-                            Method::Znum => "znum",
-                            Method::Prefix => "prefix",
-                            Method::Append => "append",
-                            Method::Uuid => "uuid",
-                        }
-                    ),
+                    &format!("--method {}", method.name()),
                 );
             }
         }
@@ -717,6 +1129,7 @@ impl Cli {
 
     fn ask_recursive(&mut self, theme: &ColorfulTheme) -> Result<()> {
         match self.recursive {
+            None if !self.interactive() => self.recursive = Some(Renamer::default().recursive),
             None => {
                 self.recursive = Some(
                     dialoguer::Confirm::with_theme(theme)
@@ -732,13 +1145,13 @@ impl Cli {
                     true,
                     "Recursively",
                     &format!("{:?}", recursive),
-                    &format!("--recursive {}", self.recursive.unwrap()),
+                    &format!("--recursive {}", recursive),
                 );
             }
         }
 
         // folder depth
-        if self.recursive.unwrap() {
+        if self.renamer().recursive {
             self.ask_depth(theme)?;
         }
         Ok(())
@@ -746,6 +1159,7 @@ impl Cli {
 
     fn ask_start_from(&mut self, theme: &ColorfulTheme) -> Result<()> {
         match self.start {
+            None if !self.interactive() => self.start = Some(Renamer::default().start),
             None => {
                 self.start = Some(
                     dialoguer::Input::with_theme(theme)
@@ -771,6 +1185,14 @@ impl Cli {
                 );
             }
         }
+        if let Some(scope) = self.counter_scope {
+            self.status_log(
+                true,
+                "Counter",
+                &format!("{:?}", scope),
+                &format!("--counter-scope {}", scope.name()),
+            );
+        }
 
         Ok(())
     }
@@ -796,7 +1218,7 @@ impl Cli {
     fn ask_nbit(&mut self, theme: &ColorfulTheme, ntotal: usize) -> Result<()> {
         //  calculate the bit_min
         let n_min = match &self.method {
-            None => anyhow::bail!("{CROSS_MARK} No task specified"),
+            None => anyhow::bail!(Error::BadInput("No method specified".into())),
             Some(method) => match method {
                 Method::Znum => ntotal.to_string().len(),
                 Method::Random => Self::decimal_to_62(ntotal).len(),
@@ -805,6 +1227,7 @@ impl Cli {
         };
         let err_msg = format!("It should be between {} to {}.", n_min, BIT_MAX);
         match self.nbits {
+            None if !self.interactive() => self.nbits = Some(Renamer::default().nbits.max(n_min)),
             None => {
                 self.nbits = Some(
                     dialoguer::Input::with_theme(theme)
@@ -830,7 +1253,10 @@ impl Cli {
             Some(n) => {
                 // validate
                 if !(n_min..=BIT_MAX).contains(&n) {
-                    self.status_log(false, "The number of bits", &n.to_string(), &err_msg);
+                    anyhow::bail!(Error::BadInput(format!(
+                        "The number of bits {}: {}",
+                        n, err_msg
+                    )));
                 }
                 self.status_log(
                     true,
@@ -845,8 +1271,9 @@ impl Cli {
     }
 
     fn ask_depth(&mut self, theme: &ColorfulTheme) -> Result<()> {
-        let max_ = self.max_depth(&self.input);
+        let max_ = self.renamer().max_depth(self.input());
         match self.depth {
+            None if !self.interactive() => {}
             None => {
                 let depth = dialoguer::Input::with_theme(theme)
                     .with_prompt("Depth")
@@ -886,6 +1313,7 @@ impl Cli {
             INVALID_CHARS
         );
         match &self.delimiter {
+            None if !self.interactive() => self.delimiter = Some(Renamer::default().delimiter),
             None => {
                 self.delimiter = Some(
                     dialoguer::Input::with_theme(theme)
@@ -923,6 +1351,11 @@ impl Cli {
             INVALID_CHARS
         );
         match &self.with {
+            None if !self.interactive() => {
+                anyhow::bail!(Error::BadInput(
+                    "Missing in non-interactive mode: --with".into()
+                ))
+            }
             None => {
                 self.with = Some(
                     dialoguer::Input::with_theme(theme)
@@ -948,6 +1381,109 @@ impl Cli {
         Ok(())
     }
 
+    fn ask_find(&mut self, theme: &ColorfulTheme) -> Result<()> {
+        match &self.find {
+            None if !self.interactive() => {
+                anyhow::bail!(Error::BadInput(
+                    "Missing in non-interactive mode: --find".into()
+                ))
+            }
+            None => {
+                self.find = Some(
+                    dialoguer::Input::with_theme(theme)
+                        .with_prompt("Find (regex)")
+                        .validate_with(|input: &String| -> Result<(), String> {
+                            match regex::Regex::new(input) {
+                                Ok(_) => Ok(()),
+                                Err(err) => Err(err.to_string()),
+                            }
+                        })
+                        .allow_empty(false)
+                        .interact_text()?,
+                );
+            }
+            Some(find) => {
+                self.status_log(true, "Find", find, &format!("--find {}", find));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ask_replace(&mut self, theme: &ColorfulTheme) -> Result<()> {
+        let err_msg = format!(
+            "Illegal characters! These are usually not allowed: {}",
+            INVALID_CHARS
+        );
+        match &self.replace {
+            None if !self.interactive() => self.replace = Some(Renamer::default().replace),
+            None => {
+                self.replace = Some(
+                    dialoguer::Input::with_theme(theme)
+                        .with_prompt("Replace with")
+                        .validate_with({
+                            |input: &String| -> Result<(), &str> {
+                                if input.as_str().chars().any(|c| INVALID_CHARS.contains(c)) {
+                                    Err(&err_msg)
+                                } else {
+                                    Ok(())
+                                }
+                            }
+                        })
+                        .allow_empty(true)
+                        .interact_text()?,
+                );
+            }
+            Some(replace) => {
+                self.status_log(
+                    true,
+                    "Replace with",
+                    replace,
+                    &format!("--replace {}", replace),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ask_template(&mut self, theme: &ColorfulTheme) -> Result<()> {
+        match &self.template {
+            None if !self.interactive() => {
+                anyhow::bail!(Error::BadInput(
+                    "Missing in non-interactive mode: --template".into()
+                ))
+            }
+            None => {
+                self.template = Some(
+                    dialoguer::Input::with_theme(theme)
+                        .with_prompt("Template")
+                        .with_initial_text("{n:04}_{stem}.{ext}".to_string())
+                        .validate_with(|input: &String| -> Result<(), String> {
+                            match Template::parse(input) {
+                                Ok(_) => Ok(()),
+                                Err(err) => Err(err.to_string()),
+                            }
+                        })
+                        .allow_empty(false)
+                        .interact_text()?,
+                );
+            }
+            Some(template) => {
+                // validate
+                Template::parse(template)?;
+                self.status_log(
+                    true,
+                    "Template",
+                    template,
+                    &format!("--template {}", template),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn build_theme() -> ColorfulTheme {
         ColorfulTheme {
             // palette: Color256(9), Color256(49)
@@ -0,0 +1,712 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Relink, RenameOp};
+
+/// Version of the journal format written
+pub const JOURNAL_VERSION: u32 = 4;
+
+/// Marks written between two syncs, also how far recovery looks beyond the last mark
+pub(crate) const SYNC_EVERY: usize = 64;
+
+/// Paths are kept as strings when valid UTF-8, or as raw bytes (unix) / wide chars (windows)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawPath {
+    Utf8(String),
+    Unix { unix: Vec<u8> },
+    Windows { windows: Vec<u16> },
+}
+
+impl From<&Path> for RawPath {
+    fn from(p: &Path) -> Self {
+        match p.to_str() {
+            Some(s) => Self::Utf8(s.to_string()),
+            None => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::ffi::OsStrExt;
+                    Self::Unix {
+                        unix: p.as_os_str().as_bytes().to_vec(),
+                    }
+                }
+                #[cfg(windows)]
+                {
+                    use std::os::windows::ffi::OsStrExt;
+                    Self::Windows {
+                        windows: p.as_os_str().encode_wide().collect(),
+                    }
+                }
+                #[cfg(not(any(unix, windows)))]
+                {
+                    Self::Utf8(p.to_string_lossy().to_string())
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<RawPath> for PathBuf {
+    type Error = anyhow::Error;
+
+    fn try_from(p: RawPath) -> Result<Self> {
+        match p {
+            RawPath::Utf8(s) => Ok(PathBuf::from(s)),
+            #[cfg(unix)]
+            RawPath::Unix { unix } => {
+                use std::os::unix::ffi::OsStringExt;
+                Ok(PathBuf::from(std::ffi::OsString::from_vec(unix)))
+            }
+            #[cfg(windows)]
+            RawPath::Windows { windows } => {
+                use std::os::windows::ffi::OsStringExt;
+                Ok(PathBuf::from(std::ffi::OsString::from_wide(&windows)))
+            }
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!(Error::Io(format!(
+                "Path recorded on another platform: {:?}",
+                p
+            ))),
+        }
+    }
+}
+
+/// One line of the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Record {
+    Header {
+        version: u32,
+        created: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        root: Option<RawPath>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        method: Option<String>,
+    },
+    /// A rename done, written by version 1 & 2
+    Rename { from: RawPath, to: RawPath },
+    /// The `seq`-th rename, about to be done
+    Intent {
+        seq: usize,
+        from: RawPath,
+        to: RawPath,
+    },
+    /// About to undo renames `seqs`, in this order
+    Undo { seqs: Vec<usize> },
+    /// About to redo renames `seqs`, in this order
+    Redo { seqs: Vec<usize> },
+    /// The `seq`-th rename was undone
+    Revert { seq: usize },
+    /// The `seq`-th rename was done or redone
+    Apply { seq: usize },
+    /// The operation started last is over
+    Commit,
+    /// A link about to be pointed at `to` instead of `from`, since version 4
+    Relink {
+        link: RawPath,
+        from: RawPath,
+        to: RawPath,
+    },
+}
+
+/// First line of the journal
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    /// `0` for legacy caches
+    pub version: u32,
+
+    /// RFC 3339, empty for legacy caches
+    pub created: String,
+
+    /// Source renamed
+    pub root: Option<PathBuf>,
+
+    /// Method used, see `Method::name()`
+    pub method: Option<String>,
+}
+
+/// A rename recorded, and whether it is in effect on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub op: RenameOp,
+    pub applied: bool,
+}
+
+/// An operation started but never committed, e.g. because of a crash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pending {
+    /// Undoing, or renaming & redoing
+    pub undo: bool,
+
+    /// Renames involved, in the order they were to happen
+    pub seqs: Vec<usize>,
+}
+
+/// History of one run, in JSON Lines: a header, then every rename on disk in order.
+///
+/// Write-ahead: renames are declared as intents before any of them happens, and marked
+/// once done. Undo & redo declare the renames involved and mark them the same way, so
+/// nothing is ever lost. Each operation ends with a commit, an operation left open was
+/// interrupted. Links retargeted once renames are done are declared before each of them is.
+///
+/// ```text
+/// {"kind":"header","version":4,"created":"2023-03-04T22:26:42.222655+08:00","root":"/data","method":"num"}
+/// {"kind":"intent","seq":0,"from":"/data/a b.jpg","to":"/data/1.jpg"}
+/// {"kind":"intent","seq":1,"from":{"unix":[120,255]},"to":"/data/2.jpg"}
+/// {"kind":"apply","seq":0}
+/// {"kind":"apply","seq":1}
+/// {"kind":"commit"}
+/// {"kind":"relink","link":"/data/latest","from":"a b.jpg","to":"1.jpg"}
+/// {"kind":"undo","seqs":[1,0]}
+/// {"kind":"revert","seq":1}
+/// {"kind":"revert","seq":0}
+/// {"kind":"commit"}
+/// ```
+pub struct Journal {
+    file: std::fs::File,
+
+    /// Marks written since the last sync
+    unsynced: std::cell::Cell<usize>,
+}
+
+impl Pending {
+    /// Renames involved not marked yet, in order
+    pub fn left(&self, entries: &[Entry]) -> Vec<usize> {
+        self.seqs
+            .iter()
+            .copied()
+            .filter(|&x| entries[x].applied == self.undo)
+            .collect()
+    }
+
+    /// Renames involved already marked, in order
+    pub fn done(&self, entries: &[Entry]) -> Vec<usize> {
+        self.seqs
+            .iter()
+            .copied()
+            .filter(|&x| entries[x].applied != self.undo)
+            .collect()
+    }
+
+    /// Renames on disk of `seqs`
+    pub fn steps(&self, entries: &[Entry], seqs: &[usize]) -> Vec<RenameOp> {
+        seqs.iter()
+            .map(|&x| match self.undo {
+                true => entries[x].op.inverse(),
+                false => entries[x].op.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Journal {
+    /// Create a new journal at `path` and write its header, held until dropped
+    pub fn create<P: AsRef<Path>>(path: P, root: &Path, method: &str) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.lock()?;
+        let journal = Self {
+            file,
+            unsynced: Default::default(),
+        };
+        journal.write(&Record::Header {
+            version: JOURNAL_VERSION,
+            created: chrono::Local::now().to_rfc3339(),
+            root: Some(root.into()),
+            method: Some(method.to_string()),
+        })?;
+        Ok(journal)
+    }
+
+    /// Open an existing journal to append marks to, dropping a last line torn by a crash
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        match Self::try_open(path)? {
+            Some(journal) => Ok(journal),
+            None => anyhow::bail!(Error::Conflict(format!(
+                "Journal {} is in use by another renify, try again once it is done.",
+                path.display()
+            ))),
+        }
+    }
+
+    /// `open()`, or none if another process holds the journal: it is held from `create()`,
+    /// or `open()`, until dropped
+    pub fn try_open<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(std::fs::TryLockError::Error(err)) => return Err(err.into()),
+        }
+        let bytes = std::fs::read(path)?;
+        if !bytes.is_empty() && !bytes.ends_with(b"\n") {
+            let n = bytes.iter().rposition(|&x| x == b'\n').map_or(0, |x| x + 1);
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(n as u64)?;
+        }
+        Ok(Some(Self {
+            file,
+            unsynced: Default::default(),
+        }))
+    }
+
+    /// Whether the last operation recorded at `path` was committed, reading its end only
+    pub fn is_closed<P: AsRef<Path>>(path: P) -> Result<bool> {
+        use std::io::{Read, Seek, SeekFrom};
+        let commit = serde_json::to_string(&Record::Commit)?;
+        let mut file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(commit.len() as u64 + 2)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        let tail = String::from_utf8_lossy(&tail);
+        Ok(tail.ends_with('\n')
+            && tail
+                .trim_end()
+                .strip_suffix(&commit)
+                .is_some_and(|x| x.is_empty() || x.ends_with('\n')))
+    }
+
+    /// Read the header only
+    pub fn read_header<P: AsRef<Path>>(path: P) -> Result<Header> {
+        let path = path.as_ref();
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let first = match reader.lines().next() {
+            None => return Ok(Header::default()),
+            Some(line) => line?,
+        };
+        match serde_json::from_str::<Record>(&first) {
+            Ok(Record::Header {
+                version,
+                created,
+                root,
+                method,
+            }) => Ok(Header {
+                version,
+                created,
+                root: root.map(|x| x.try_into()).transpose()?,
+                method,
+            }),
+            _ => Ok(Header::default()),
+        }
+    }
+
+    /// Record a rename that just happened on disk
+    pub fn record(&self, op: &RenameOp) -> Result<()> {
+        self.write(&Record::Rename {
+            from: op.from.as_path().into(),
+            to: op.to.as_path().into(),
+        })
+    }
+
+    /// Declare renames about to happen, in this order, before any of them does
+    pub fn intend(&self, steps: &[RenameOp]) -> Result<()> {
+        let mut lines = String::new();
+        for (seq, op) in steps.iter().enumerate() {
+            lines.push_str(&serde_json::to_string(&Record::Intent {
+                seq,
+                from: op.from.as_path().into(),
+                to: op.to.as_path().into(),
+            })?);
+            lines.push('\n');
+        }
+        (&self.file).write_all(lines.as_bytes())?;
+        self.sync()
+    }
+
+    /// Declare renames `seqs` about to be undone, or redone, in this order
+    pub fn begin(&self, undo: bool, seqs: &[usize]) -> Result<()> {
+        let seqs = seqs.to_vec();
+        self.write(&match undo {
+            true => Record::Undo { seqs },
+            false => Record::Redo { seqs },
+        })?;
+        self.sync()
+    }
+
+    /// Declare a link about to be retargeted, once renames are done
+    pub fn relink(&self, relink: &Relink) -> Result<()> {
+        self.write(&Record::Relink {
+            link: relink.link.as_path().into(),
+            from: relink.from.as_path().into(),
+            to: relink.to.as_path().into(),
+        })?;
+        self.sync()
+    }
+
+    /// Links retargeted, or about to be, in this order.
+    /// Whether they were is told by where they point on disk.
+    pub fn relinks<P: AsRef<Path>>(path: P) -> Result<Vec<Relink>> {
+        let path = path.as_ref();
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut ys = Vec::new();
+        for line in reader.lines() {
+            if let Ok(Record::Relink { link, from, to }) = serde_json::from_str::<Record>(&line?) {
+                ys.push(Relink {
+                    link: link.try_into()?,
+                    from: from.try_into()?,
+                    to: to.try_into()?,
+                });
+            }
+        }
+        Ok(ys)
+    }
+
+    /// Mark the `seq`-th rename as undone
+    pub fn revert(&self, seq: usize) -> Result<()> {
+        self.mark(&Record::Revert { seq })
+    }
+
+    /// Mark the `seq`-th rename as done or redone
+    pub fn apply(&self, seq: usize) -> Result<()> {
+        self.mark(&Record::Apply { seq })
+    }
+
+    /// End the operation started last
+    pub fn commit(&self) -> Result<()> {
+        self.write(&Record::Commit)?;
+        self.sync()
+    }
+
+    fn mark(&self, record: &Record) -> Result<()> {
+        self.write(record)?;
+        self.unsynced.set(self.unsynced.get() + 1);
+        if self.unsynced.get() >= SYNC_EVERY {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.file.sync_data()?;
+        self.unsynced.set(0);
+        Ok(())
+    }
+
+    fn write(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        (&self.file).write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Read back renames recorded at `path`, in the order they happened, whatever their state.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<RenameOp>> {
+        Ok(Self::entries(path)?.into_iter().map(|x| x.op).collect())
+    }
+
+    /// Read back renames recorded at `path`, in the order they happened, with their state.
+    pub fn entries<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>> {
+        Ok(Self::load(path)?.0)
+    }
+
+    /// The operation left open at `path`, if any
+    pub fn pending<P: AsRef<Path>>(path: P) -> Result<Option<Pending>> {
+        Ok(Self::load(path)?.1)
+    }
+
+    /// Renames with their state, and the operation left open.
+    ///
+    /// Caches written before the journal format (`<old> <new>` per line) are still read,
+    /// unless a path contains a space, which makes them ambiguous. A last line torn by
+    /// a crash is ignored.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(Vec<Entry>, Option<Pending>)> {
+        let path = path.as_ref();
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut lines = reader.lines();
+        let first = match lines.next() {
+            None => return Ok((Vec::new(), None)),
+            Some(line) => line?,
+        };
+
+        // legacy
+        let version = match serde_json::from_str::<Record>(&first) {
+            Ok(Record::Header { version, .. }) => version,
+            _ => {
+                let ys = Self::read_legacy(path, std::iter::once(Ok(first)).chain(lines))?
+                    .into_iter()
+                    .map(|op| Entry { op, applied: true })
+                    .collect();
+                return Ok((ys, None));
+            }
+        };
+        if version > JOURNAL_VERSION {
+            anyhow::bail!(Error::Io(format!(
+                "Journal {} is version {}, newer than supported ({}). Please upgrade renify.",
+                path.display(),
+                version,
+                JOURNAL_VERSION
+            )));
+        }
+
+        let mut ys = Vec::new();
+        let mut pending: Option<Pending> = None;
+        let mut lines = lines.enumerate().peekable();
+        while let Some((i, line)) = lines.next() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(&line) {
+                Ok(Record::Rename { from, to }) => ys.push(Entry {
+                    op: RenameOp {
+                        from: from.try_into()?,
+                        to: to.try_into()?,
+                    },
+                    applied: true,
+                }),
+                Ok(Record::Intent { seq, from, to }) if seq == ys.len() => {
+                    ys.push(Entry {
+                        op: RenameOp {
+                            from: from.try_into()?,
+                            to: to.try_into()?,
+                        },
+                        applied: false,
+                    });
+                    match pending.as_mut() {
+                        Some(x) if !x.undo => x.seqs.push(seq),
+                        _ => {
+                            pending = Some(Pending {
+                                undo: false,
+                                seqs: vec![seq],
+                            })
+                        }
+                    }
+                }
+                Ok(Record::Undo { seqs }) if seqs.iter().all(|&x| x < ys.len()) => {
+                    pending = Some(Pending { undo: true, seqs })
+                }
+                Ok(Record::Redo { seqs }) if seqs.iter().all(|&x| x < ys.len()) => {
+                    pending = Some(Pending { undo: false, seqs })
+                }
+                Ok(Record::Revert { seq }) if seq < ys.len() => ys[seq].applied = false,
+                Ok(Record::Apply { seq }) if seq < ys.len() => ys[seq].applied = true,
+                Ok(Record::Commit) => pending = None,
+                Ok(Record::Header { .. } | Record::Relink { .. }) => {}
+                Ok(_) => anyhow::bail!(Error::Io(format!(
+                    "Journal {} is corrupted at line {}: unknown rename",
+                    path.display(),
+                    i + 2
+                ))),
+                // torn by a crash
+                Err(_) if lines.peek().is_none() => {}
+                Err(err) => anyhow::bail!(Error::Io(format!(
+                    "Journal {} is corrupted at line {}: {}",
+                    path.display(),
+                    i + 2,
+                    err
+                ))),
+            }
+        }
+        Ok((ys, pending))
+    }
+
+    fn read_legacy<I>(path: &Path, lines: I) -> Result<Vec<RenameOp>>
+    where
+        I: Iterator<Item = std::io::Result<String>>,
+    {
+        let mut ys = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let v: Vec<&str> = line.split(' ').collect();
+            if v.len() != 2 {
+                anyhow::bail!(Error::Io(format!("Legacy cache {} is ambiguous at line {}: paths with spaces can not be told apart.",
+                    path.display(),
+                    i + 1)));
+            }
+            ys.push(RenameOp {
+                from: PathBuf::from(v[0]),
+                to: PathBuf::from(v[1]),
+            });
+        }
+        Ok(ys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(from: &str, to: &str) -> RenameOp {
+        RenameOp {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("x.jsonl");
+        let journal = Journal::create(&path, Path::new("/data"), "num").unwrap();
+        let steps = vec![op("/data/a", "/data/1"), op("/data/b", "/data/2")];
+        journal.intend(&steps).unwrap();
+        journal.apply(0).unwrap();
+        journal.apply(1).unwrap();
+        journal.commit().unwrap();
+
+        let header = Journal::read_header(&path).unwrap();
+        assert_eq!(header.version, JOURNAL_VERSION);
+        assert_eq!(header.root, Some(PathBuf::from("/data")));
+        assert_eq!(header.method.as_deref(), Some("num"));
+        let (entries, pending) = Journal::load(&path).unwrap();
+        assert_eq!(pending, None);
+        assert_eq!(Journal::read(&path).unwrap(), steps);
+        assert!(entries.iter().all(|x| x.applied));
+
+        // undone
+        journal.begin(true, &[1, 0]).unwrap();
+        journal.revert(1).unwrap();
+        journal.revert(0).unwrap();
+        journal.commit().unwrap();
+        let (entries, pending) = Journal::load(&path).unwrap();
+        assert_eq!(pending, None);
+        assert!(entries.iter().all(|x| !x.applied));
+    }
+
+    #[test]
+    fn unclosed() {
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("x.jsonl");
+        let journal = Journal::create(&path, Path::new("/data"), "num").unwrap();
+        let steps = vec![
+            op("/data/a", "/data/1"),
+            op("/data/b", "/data/2"),
+            op("/data/c", "/data/3"),
+        ];
+        journal.intend(&steps).unwrap();
+        journal.apply(0).unwrap();
+        drop(journal);
+
+        let (entries, pending) = Journal::load(&path).unwrap();
+        let pending = pending.unwrap();
+        assert_eq!(
+            pending,
+            Pending {
+                undo: false,
+                seqs: vec![0, 1, 2]
+            }
+        );
+        assert_eq!(pending.left(&entries), vec![1, 2]);
+        assert_eq!(pending.done(&entries), vec![0]);
+        assert_eq!(pending.steps(&entries, &[1]), vec![steps[1].clone()]);
+
+        // an undo left open walks renames backwards
+        let journal = Journal::open(&path).unwrap();
+        journal.commit().unwrap();
+        journal.begin(true, &[0]).unwrap();
+        let (entries, pending) = Journal::load(&path).unwrap();
+        let pending = pending.unwrap();
+        assert_eq!(pending.left(&entries), vec![0]);
+        assert_eq!(pending.steps(&entries, &[0]), vec![steps[0].inverse()]);
+    }
+
+    #[test]
+    fn locked() {
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("x.jsonl");
+        let journal = Journal::create(&path, Path::new("/data"), "num").unwrap();
+        journal.intend(&[op("/data/a", "/data/1")]).unwrap();
+        assert!(!Journal::is_closed(&path).unwrap());
+        // held by a renify still running
+        assert!(Journal::try_open(&path).unwrap().is_none());
+        assert!(Journal::open(&path).is_err());
+
+        journal.apply(0).unwrap();
+        journal.commit().unwrap();
+        assert!(Journal::is_closed(&path).unwrap());
+        drop(journal);
+        let journal = Journal::try_open(&path).unwrap().unwrap();
+        journal.begin(true, &[0]).unwrap();
+        assert!(!Journal::is_closed(&path).unwrap());
+    }
+
+    #[test]
+    fn torn() {
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("x.jsonl");
+        let journal = Journal::create(&path, Path::new("/data"), "num").unwrap();
+        journal.intend(&[op("/data/a", "/data/1")]).unwrap();
+        drop(journal);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"{\"kind\":\"app");
+        std::fs::write(&path, &bytes).unwrap();
+
+        // ignored when read, dropped when appended to
+        let (entries, pending) = Journal::load(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(!entries[0].applied);
+        assert!(pending.is_some());
+        let journal = Journal::open(&path).unwrap();
+        journal.apply(0).unwrap();
+        journal.commit().unwrap();
+        let (entries, pending) = Journal::load(&path).unwrap();
+        assert!(entries[0].applied);
+        assert_eq!(pending, None);
+
+        // anywhere else, it is corrupted
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"{\"kind\":\"app\n{\"kind\":\"commit\"}\n");
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Journal::load(&path).is_err());
+    }
+
+    #[test]
+    fn legacy() {
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("20230304222642.txt");
+        std::fs::write(&path, "a.jpg 1.jpg\nb.jpg 2.jpg\n").unwrap();
+        assert_eq!(Journal::read_header(&path).unwrap().version, 0);
+        let (entries, pending) = Journal::load(&path).unwrap();
+        assert_eq!(pending, None);
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    op: op("a.jpg", "1.jpg"),
+                    applied: true
+                },
+                Entry {
+                    op: op("b.jpg", "2.jpg"),
+                    applied: true
+                },
+            ]
+        );
+
+        // ambiguous
+        std::fs::write(&path, "a b.jpg 1.jpg\n").unwrap();
+        assert!(Journal::load(&path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raw_path() {
+        use std::os::unix::ffi::OsStrExt;
+        let d = tempfile::tempdir().unwrap();
+        let path = d.path().join("x.jsonl");
+        let from = PathBuf::from(std::ffi::OsStr::from_bytes(b"/data/x\xff.jpg"));
+        let step = RenameOp {
+            from: from.clone(),
+            to: PathBuf::from("/data/1.jpg"),
+        };
+        let journal = Journal::create(&path, Path::new("/data"), "num").unwrap();
+        journal.intend(std::slice::from_ref(&step)).unwrap();
+        journal.apply(0).unwrap();
+        journal.commit().unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("\"unix\":[47,100,97,116,97,47,120,255"));
+        assert_eq!(Journal::read(&path).unwrap(), vec![step]);
+    }
+}
@@ -1,15 +1,35 @@
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 
+mod case;
 mod cli;
+mod counter;
+mod error;
+mod filter;
+mod history;
 mod impl_;
+mod journal;
 mod method;
+mod plan;
+mod renamer;
+mod report;
+mod sort;
 mod target;
 mod task;
+mod template;
 
-pub use cli::Cli;
+pub use cli::{Cli, Command, HistoryCommand};
+pub use counter::CounterScope;
+pub use error::{exit_code, Error};
+pub use history::{History, Session, SessionState, HISTORY_DIR_ENV};
+pub use journal::{Entry, Header, Journal, Pending, JOURNAL_VERSION};
 pub use method::Method;
+pub use plan::{interrupt, Relink, RenameOp, RenamePlan};
+pub use renamer::{Renamer, Targets};
+pub use report::{FileReport, FileStatus, Output, Report};
+pub use sort::{natural, Sort};
 pub use target::Target;
 pub use task::Task;
+pub use template::Template;
 
 /// illegal characters
 const INVALID_CHARS: &str = "<>:/\"|?*'`";
@@ -17,6 +37,76 @@ const BIT_MAX: usize = 20;
 const CROSS_MARK: &str = "❌";
 const CHECK_MARK: &str = "✅";
 
+/// Whether something exists at `p`, without following symlinks
+fn exists_nofollow(p: &std::path::Path) -> bool {
+    p.symlink_metadata().is_ok()
+}
+
+/// Absolute `p`, with symlinks resolved except `p` itself when it is one
+fn canonicalize_nofollow(p: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+    match (p.is_symlink(), p.parent(), p.file_name()) {
+        (true, Some(parent), Some(name)) => {
+            let parent = match parent.as_os_str().is_empty() {
+                true => std::path::Path::new("."),
+                false => parent,
+            };
+            Ok(parent.canonicalize()?.join(name))
+        }
+        _ => p.canonicalize(),
+    }
+}
+
+/// `p` with `.` & `..` resolved, without touching the filesystem
+fn normalize(p: &std::path::Path) -> std::path::PathBuf {
+    use std::path::Component;
+    let mut ys = std::path::PathBuf::new();
+    for x in p.components() {
+        match x {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !ys.pop() {
+                    ys.push(x);
+                }
+            }
+            _ => ys.push(x),
+        }
+    }
+    ys
+}
+
+/// `target` relative to the folder `base`, both absolute, e.g. `../b/x` for `/a/b/x` from `/a/c`
+fn relative_path(target: &std::path::Path, base: &std::path::Path) -> std::path::PathBuf {
+    let (xs, ys): (Vec<_>, Vec<_>) = (target.components().collect(), base.components().collect());
+    let n = xs.iter().zip(ys.iter()).take_while(|(x, y)| x == y).count();
+    let mut p = std::path::PathBuf::new();
+    for _ in n..ys.len() {
+        p.push("..");
+    }
+    for x in xs[n..].iter() {
+        p.push(x);
+    }
+    if p.as_os_str().is_empty() {
+        p.push(".");
+    }
+    p
+}
+
+/// Whether `a` and `b` are the same entry on disk, e.g. `a.jpg` & `A.jpg` on case-insensitive volumes
+fn is_same_entry(a: &std::path::Path, b: &std::path::Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (a.symlink_metadata(), b.symlink_metadata()) {
+            (Ok(x), Ok(y)) => x.dev() == y.dev() && x.ino() == y.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        same_file::is_same_file(a, b).unwrap_or(false)
+    }
+}
+
 fn build_progressbar(size: u64, prefix: &str) -> ProgressBar {
     let pb = ProgressBar::new(size);
     pb.set_style(
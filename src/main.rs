@@ -1,6 +1,19 @@
 use clap::Parser;
 
-fn main() -> anyhow::Result<()> {
-    renify::Cli::parse().run()?;
-    Ok(())
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {:?}", err);
+        std::process::exit(renify::exit_code(&err));
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    // renames under way stop cleanly, anything else, or a second signal, quits right away
+    ctrlc::set_handler(|| {
+        if !renify::interrupt() {
+            let _ = console::Term::stdout().show_cursor();
+            std::process::exit(130);
+        }
+    })?;
+    renify::Cli::parse().run()
 }
@@ -1,41 +1,82 @@
-#[derive(Debug, Clone, clap::ValueEnum)]
-pub enum Method {
-    /// => 9AFoh, wGRLC, knj9y, ... (--nbits => 5)
-    Random,
-    /// => de2662a9-fb02-4686-b556-0aca36c0e087
-    Uuid,
-    /// => 2023-03-04-22-26-42-222655555  (--delimiter => -)
-    Time,
-    /// => 1, 2, 3, ... (--start => 1)
-    Num,
-    /// => 001, 002, 003, ... (--nbits => 3)
-    Znum,
-    /// => X.jpg --> <Prefix><Delimiter>X.jpg
-    Prefix,
-    /// => X.jpg --> X<Delimiter><Append>.jpg
-    Append,
-    // TODO: OS problem, unsafe
-    // windows: unsupported ,NTFS
-    // Linux: supported, ext4
-    // maxos: unsupported, APFS
-    // Uppercase,
-    // Lowercase,
-    // Capitalized,
-}
-
-impl From<&str> for Method {
-    fn from(s: &str) -> Self {
-        match s {
-            "Random" => Self::Random,
-            "Uuid" => Self::Uuid,
-            "Time" => Self::Time,
-            "Numbered" => Self::Num,
-            "ZeroNumbered" => Self::Znum,
-            "Prefix" => Self::Prefix,
-            "Append" => Self::Append,
-            // "Uppercase" => Self::Uppercase,
-            // "Lowercase" => Self::Lowercase,
-            _ => todo!(),
-        }
-    }
-}
+#[derive(Debug, Clone, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    /// => 9AFoh, wGRLC, knj9y, ... (--nbits => 5)
+    Random,
+    /// => de2662a9-fb02-4686-b556-0aca36c0e087
+    Uuid,
+    /// => 2023-03-04-22-26-42-222655555  (--delimiter => -)
+    Time,
+    /// => 1, 2, 3, ... (--start => 1)
+    Num,
+    /// => 001, 002, 003, ... (--nbits => 3)
+    Znum,
+    /// => X.jpg --> <Prefix><Delimiter>X.jpg
+    Prefix,
+    /// => X.jpg --> X<Delimiter><Append>.jpg
+    Append,
+    /// => my file.jpg --> MY FILE.jpg
+    Uppercase,
+    /// => My File.jpg --> my file.jpg
+    Lowercase,
+    /// => my file-name.jpg --> My File-Name.jpg
+    Title,
+    /// => My File-Name.jpg --> my_file_name.jpg
+    Snake,
+    /// => My File_Name.jpg --> my-file-name.jpg
+    Kebab,
+    /// => my file_name.jpg --> myFileName.jpg
+    Camel,
+    /// => 0001_IMG.jpg --> IMG_0001.jpg (--find '(\d+)_IMG' --replace 'IMG_$1')
+    Regex,
+    /// => X.jpg --> photos_0001_x.jpg (--template '{parent}_{n:04}_{stem|lower}.{ext}')
+    Template,
+}
+
+impl TryFrom<&str> for Method {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "Random" => Self::Random,
+            "Uuid" => Self::Uuid,
+            "Time" => Self::Time,
+            "Numbered" => Self::Num,
+            "ZeroNumbered" => Self::Znum,
+            "Prefix" => Self::Prefix,
+            "Append" => Self::Append,
+            "Uppercase" => Self::Uppercase,
+            "Lowercase" => Self::Lowercase,
+            "Title" => Self::Title,
+            "SnakeCase" => Self::Snake,
+            "KebabCase" => Self::Kebab,
+            "CamelCase" => Self::Camel,
+            "Regex" => Self::Regex,
+            "Template" => Self::Template,
+            _ => anyhow::bail!(crate::Error::BadInput(format!("Unknown method: {s}"))),
+        })
+    }
+}
+
+impl Method {
+    /// Name used by `--method`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::Time => "time",
+            Self::Num => "num",
+            Self::Znum => "znum",
+            Self::Prefix => "prefix",
+            Self::Append => "append",
+            Self::Uuid => "uuid",
+            Self::Uppercase => "uppercase",
+            Self::Lowercase => "lowercase",
+            Self::Title => "title",
+            Self::Snake => "snake",
+            Self::Kebab => "kebab",
+            Self::Camel => "camel",
+            Self::Regex => "regex",
+            Self::Template => "template",
+        }
+    }
+}
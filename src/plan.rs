@@ -0,0 +1,730 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Error;
+
+/// Whether renames are under way
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Whether renames under way were asked to stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ask renames under way, if any, to stop as soon as no file is left under a temporary name.
+/// Meant for signal handlers: returns `false` when there is nothing to stop, or when it was
+/// asked already.
+pub fn interrupt() -> bool {
+    BUSY.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst)
+}
+
+/// Marks renames under way, until dropped
+struct Busy;
+
+impl Busy {
+    fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        BUSY.store(true, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A single planned renaming: `from` → `to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameOp {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl RenameOp {
+    /// `to` → `from`
+    pub fn inverse(&self) -> Self {
+        Self {
+            from: self.to.clone(),
+            to: self.from.clone(),
+        }
+    }
+}
+
+/// A symlink pointed elsewhere: `link` → `from` becomes `link` → `to`.
+/// `link` is where the link is once renames are done, `from` & `to` are its contents.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Relink {
+    pub link: PathBuf,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl Relink {
+    /// Point the link at `to`. Done already is fine, anything else but `from` is a conflict.
+    pub fn apply(&self) -> Result<()> {
+        Self::set(&self.link, &self.from, &self.to)
+    }
+
+    /// Point the link back at `from`
+    pub fn revert(&self) -> Result<()> {
+        Self::set(&self.link, &self.to, &self.from)
+    }
+
+    /// Replace the link pointing at `from` with one pointing at `to`, in a single rename
+    fn set(link: &Path, from: &Path, to: &Path) -> Result<()> {
+        // removed since, nothing to point
+        if !crate::exists_nofollow(link) {
+            return Ok(());
+        }
+        let current = std::fs::read_link(link)
+            .map_err(|err| Error::Conflict(format!("Not a link: {}, {}", link.display(), err)))?;
+        if current == to {
+            return Ok(());
+        }
+        if current != from {
+            anyhow::bail!(Error::Conflict(format!(
+                "Link changed: {} → {}, expected → {}",
+                link.display(),
+                current.display(),
+                from.display()
+            )));
+        }
+        let tmp = RenamePlan::temp_name(link);
+        Self::symlink(to, &tmp, link)?;
+        if let Err(err) = std::fs::rename(&tmp, link) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Create `link` pointing at `target`, of the same kind as `like` on Windows
+    fn symlink(target: &Path, link: &Path, like: &Path) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let _ = like;
+            std::os::unix::fs::symlink(target, link)?;
+        }
+        #[cfg(windows)]
+        {
+            match like.metadata().map(|x| x.is_dir()).unwrap_or(false) {
+                true => std::os::windows::fs::symlink_dir(target, link)?,
+                false => std::os::windows::fs::symlink_file(target, link)?,
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (target, link, like);
+            anyhow::bail!(Error::BadInput("Symlinks are not supported here".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Planned renaming, in the order it will be applied.
+/// Ops can be inspected or mutated before being applied.
+///
+/// Ops may target the current name of another op (e.g. `1 → 2, 2 → 3`, or swaps),
+/// `steps()` orders them so that nothing gets overwritten.
+#[derive(Debug, Clone, Default)]
+pub struct RenamePlan {
+    pub ops: Vec<RenameOp>,
+
+    /// Links to point at the new names, once renames are done
+    pub relinks: Vec<Relink>,
+}
+
+impl RenamePlan {
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, RenameOp> {
+        self.ops.iter()
+    }
+
+    /// Net `from` → `to` of renames on disk, e.g. `a → tmp, tmp → b` gives `a → b`
+    pub fn compose(steps: &[RenameOp]) -> Self {
+        let mut ops: Vec<RenameOp> = Vec::new();
+        let mut current: HashMap<PathBuf, usize> = HashMap::new();
+        for step in steps.iter() {
+            match current.remove(&step.from) {
+                Some(i) => {
+                    ops[i].to = step.to.clone();
+                    current.insert(step.to.clone(), i);
+                }
+                None => {
+                    current.insert(step.to.clone(), ops.len());
+                    ops.push(step.clone());
+                }
+            }
+        }
+        ops.retain(|x| x.from != x.to);
+        Self {
+            ops,
+            ..Default::default()
+        }
+    }
+
+    /// Where `p` is once the plan is applied, e.g. below a folder renamed
+    pub fn map(&self, p: &Path) -> PathBuf {
+        let mut p = p.to_path_buf();
+        for op in self.ops.iter() {
+            if let Ok(rest) = p.strip_prefix(&op.from) {
+                p = match rest.as_os_str().is_empty() {
+                    true => op.to.clone(),
+                    false => op.to.join(rest),
+                };
+            }
+        }
+        p
+    }
+
+    /// Apply the plan, as a whole or not at all.
+    pub fn apply(&self) -> Result<()> {
+        self.apply_with(|_| Ok(()))
+    }
+
+    /// Apply the plan, calling `f` after each rename on disk.
+    pub fn apply_with<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&RenameOp) -> Result<()>,
+    {
+        let steps = self.steps();
+        let n = Self::apply_steps(&steps, |_, op| f(op), |_, _| Ok(()))?;
+        if n < steps.len() {
+            anyhow::bail!(Error::Cancelled(format!(
+                "Interrupted after {} of {} renames",
+                n,
+                steps.len()
+            )));
+        }
+        for x in self.relinks.iter() {
+            x.apply()?;
+        }
+        Ok(())
+    }
+
+    /// Apply steps computed by `steps()` as one batch, calling `f` with its index after each
+    /// rename on disk. Existing entries are never overwritten.
+    ///
+    /// On the first failure, of a rename or of `f`, renames done so far are reverted in reverse
+    /// order, calling `g` after each of them. The error tells which rename failed and how the
+    /// rollback went, and is caused by why it failed.
+    ///
+    /// Once `interrupt()` is called, it stops as soon as no file is left under a temporary name.
+    /// Gives how many steps were done, all of them unless interrupted.
+    pub fn apply_steps<F, G>(steps: &[RenameOp], mut f: F, mut g: G) -> Result<usize>
+    where
+        F: FnMut(usize, &RenameOp) -> Result<()>,
+        G: FnMut(usize, &RenameOp) -> Result<()>,
+    {
+        let _busy = Busy::new();
+
+        // where each name is taken from last, & temporary names in use
+        let last_from: HashMap<&Path, usize> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.from.as_path(), i))
+            .collect();
+        let mut temps: HashSet<&Path> = HashSet::new();
+
+        for (i, step) in steps.iter().enumerate() {
+            if temps.is_empty() && INTERRUPTED.load(Ordering::SeqCst) {
+                return Ok(i);
+            }
+            let err = match Self::rename(step) {
+                Err(err) => err,
+                Ok(()) => match f(i, step) {
+                    Ok(()) => {
+                        temps.remove(step.from.as_path());
+                        if last_from.get(step.to.as_path()).is_some_and(|&k| k > i) {
+                            temps.insert(&step.to);
+                        }
+                        continue;
+                    }
+                    // done but not reported, revert it alone
+                    Err(err) => match Self::rename(&step.inverse()) {
+                        Ok(()) => err,
+                        Err(x) => {
+                            return Err(err.context(format!(
+                                "Failed to rename {} => {}, rollback failed at its first step: {}, {} renames are still applied",
+                                step.from.display(),
+                                step.to.display(),
+                                x,
+                                i + 1
+                            )))
+                        }
+                    },
+                },
+            };
+            let outcome = match Self::rollback(&steps[..i], &mut g) {
+                Ok(()) => format!("{} renames rolled back", i),
+                Err((k, x)) => format!(
+                    "rollback failed at {} => {}: {}, {} renames are still applied",
+                    steps[k].to.display(),
+                    steps[k].from.display(),
+                    x,
+                    k + 1
+                ),
+            };
+            return Err(err.context(format!(
+                "Failed to rename {} => {}, {}",
+                step.from.display(),
+                step.to.display(),
+                outcome
+            )));
+        }
+        Ok(steps.len())
+    }
+
+    /// Revert `steps` in reverse order, calling `g` after each of them.
+    /// Stops at the first one failing, giving its index.
+    fn rollback<G>(steps: &[RenameOp], g: &mut G) -> std::result::Result<(), (usize, anyhow::Error)>
+    where
+        G: FnMut(usize, &RenameOp) -> Result<()>,
+    {
+        for (k, step) in steps.iter().enumerate().rev() {
+            Self::rename(&step.inverse()).map_err(|x| (k, x))?;
+            // files come first, a failure to report is not a reason to stop
+            let _ = g(k, step);
+        }
+        Ok(())
+    }
+
+    /// Rename on disk, never overwriting
+    fn rename(step: &RenameOp) -> Result<()> {
+        if crate::exists_nofollow(&step.to) {
+            anyhow::bail!(Error::Conflict(format!(
+                "Conflict: {} => {} already exists.",
+                step.from.display(),
+                step.to.display()
+            )));
+        }
+        std::fs::rename(&step.from, &step.to)?;
+        Ok(())
+    }
+
+    /// Check, without touching anything, that `steps` can be applied on disk as they are:
+    /// every `from` is in place and every `to` is free by the time it is renamed.
+    /// Folders renamed take their contents along.
+    pub fn check_steps(steps: &[RenameOp]) -> Result<()> {
+        // paths whose existence has changed along the way, as named on disk beforehand,
+        // & folders among them
+        let mut changed: HashMap<PathBuf, bool> = HashMap::new();
+        let mut dirs: HashSet<PathBuf> = HashSet::new();
+        let mut vacated: Vec<PathBuf> = Vec::new();
+
+        // folders renamed so far, & the name on disk beforehand of a path now,
+        // `None` for paths left in a folder renamed
+        let mut moves: Vec<(&Path, &Path)> = Vec::new();
+        let key = |p: &Path, moves: &[(&Path, &Path)]| -> Option<PathBuf> {
+            let mut p = p.to_path_buf();
+            for (from, to) in moves.iter().rev() {
+                match p.strip_prefix(to) {
+                    Ok(rest) if !rest.as_os_str().is_empty() => p = from.join(rest),
+                    Ok(_) => {}
+                    Err(_) => {
+                        if p.strip_prefix(from)
+                            .is_ok_and(|x| !x.as_os_str().is_empty())
+                        {
+                            return None;
+                        }
+                    }
+                }
+            }
+            Some(p)
+        };
+        let exists = |p: &Option<PathBuf>, changed: &HashMap<PathBuf, bool>| match p {
+            None => false,
+            Some(p) => match changed.get(p) {
+                Some(&x) => x,
+                None => crate::exists_nofollow(p),
+            },
+        };
+
+        for step in steps.iter() {
+            let from = key(&step.from, &moves);
+            if !exists(&from, &changed) {
+                anyhow::bail!(Error::Conflict(format!(
+                    "Missing: {} is not in place anymore.",
+                    step.from.display()
+                )));
+            }
+            let to = key(&step.to, &moves);
+            // case-insensitive volumes: a name vacated before under another case is free
+            let taken = match (&to, to.as_ref().and_then(|x| changed.get(x))) {
+                (_, Some(&x)) => x,
+                (None, None) => true,
+                (Some(to), None) => {
+                    crate::exists_nofollow(to)
+                        && !vacated.iter().any(|x| crate::is_same_entry(x, to))
+                }
+            };
+            if taken {
+                anyhow::bail!(Error::Conflict(format!(
+                    "Conflict: {} => {} already exists.",
+                    step.from.display(),
+                    step.to.display()
+                )));
+            }
+            let (from, to) = (from.unwrap_or_default(), to.unwrap_or_default());
+            let is_dir = match changed.contains_key(&from) {
+                true => dirs.contains(&from),
+                false => std::fs::symlink_metadata(&from).is_ok_and(|x| x.is_dir()),
+            };
+            changed.insert(from.clone(), false);
+            changed.insert(to.clone(), true);
+            if is_dir {
+                dirs.insert(to);
+                moves.push((&step.from, &step.to));
+            }
+            vacated.push(from);
+        }
+        Ok(())
+    }
+
+    /// How many of `steps`, looking at most `window` ahead, are on disk already:
+    /// the longest run from the first one whose outcome matches the filesystem.
+    pub fn applied_prefix(steps: &[RenameOp], window: usize) -> usize {
+        for n in (1..=window.min(steps.len())).rev() {
+            let mut expected: HashMap<&Path, bool> = HashMap::new();
+            for step in steps[..n].iter() {
+                expected.insert(&step.from, false);
+                expected.insert(&step.to, true);
+            }
+            if expected
+                .iter()
+                .all(|(p, &x)| crate::exists_nofollow(p) == x)
+            {
+                return n;
+            }
+        }
+        0
+    }
+
+    /// Renames on disk, in the order they should happen.
+    ///
+    /// Ops of the same directory are ordered so that a name is vacated before being taken,
+    /// chains like `1 → 2, 2 → 3` become `2 → 3, 1 → 2`, wherever they are in the plan. Ops
+    /// moving entries across folders are ordered along with both. Cycles like swaps are broken
+    /// by routing one op through a temporary name, which shows up as two steps. So are case-only
+    /// changes on case-insensitive volumes, where `from` & `to` are the same entry.
+    pub fn steps(&self) -> Vec<RenameOp> {
+        // folders whose names depend on each other, joined by the ops moving entries across
+        let mut folders: HashMap<&Path, usize> = HashMap::new();
+        for op in self.ops.iter() {
+            for p in [&op.from, &op.to] {
+                let n = folders.len();
+                folders.entry(p.parent().unwrap_or(p)).or_insert(n);
+            }
+        }
+        let folder = |p: &Path| folders[p.parent().unwrap_or(p)];
+        let mut link: Vec<usize> = (0..folders.len()).collect();
+        fn root(link: &mut [usize], mut x: usize) -> usize {
+            while link[x] != x {
+                link[x] = link[link[x]];
+                x = link[x];
+            }
+            x
+        }
+        for op in self.ops.iter() {
+            let x = root(&mut link, folder(&op.from));
+            let y = root(&mut link, folder(&op.to));
+            link[y] = x;
+        }
+
+        // groups in the order they first show up, deepest first as planned
+        let mut groups: Vec<Vec<RenameOp>> = Vec::new();
+        let mut index: HashMap<usize, usize> = HashMap::new();
+        for op in self.ops.iter() {
+            let key = root(&mut link, folder(&op.from));
+            let n = groups.len();
+            let k = *index.entry(key).or_insert(n);
+            if k == n {
+                groups.push(Vec::new());
+            }
+            groups[k].push(op.clone());
+        }
+        let mut steps = Vec::with_capacity(self.ops.len());
+        for ops in groups.iter() {
+            Self::order(ops, &mut steps);
+        }
+        steps
+    }
+
+    fn order(ops: &[RenameOp], steps: &mut Vec<RenameOp>) {
+        // names not vacated yet & ops waiting for them
+        let mut pending: HashMap<&Path, usize> = ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (op.from.as_path(), i))
+            .collect();
+        let waiting: HashMap<&Path, usize> = ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (op.to.as_path(), i))
+            .collect();
+        let mut froms: Vec<PathBuf> = ops.iter().map(|op| op.from.clone()).collect();
+        let mut done = vec![false; ops.len()];
+        let mut ready: std::collections::VecDeque<usize> = (0..ops.len())
+            .filter(|&i| !pending.contains_key(ops[i].to.as_path()))
+            .collect();
+        let mut cursor = 0;
+        let mut ndone = 0;
+
+        // case-only changes on case-insensitive volumes: `to` is `from` itself, go via a temporary name
+        for (k, op) in ops.iter().enumerate() {
+            if op.from != op.to
+                && !pending.contains_key(op.to.as_path())
+                && crate::is_same_entry(&op.from, &op.to)
+            {
+                let tmp = Self::temp_name(&op.from);
+                steps.push(RenameOp {
+                    from: froms[k].clone(),
+                    to: tmp.clone(),
+                });
+                froms[k] = tmp;
+                pending.remove(op.from.as_path());
+                if let Some(&x) = waiting.get(op.from.as_path()) {
+                    ready.push_back(x);
+                }
+            }
+        }
+
+        while ndone < ops.len() {
+            while let Some(i) = ready.pop_front() {
+                steps.push(RenameOp {
+                    from: froms[i].clone(),
+                    to: ops[i].to.clone(),
+                });
+                done[i] = true;
+                ndone += 1;
+                if pending.remove(ops[i].from.as_path()).is_some() {
+                    if let Some(&k) = waiting.get(ops[i].from.as_path()) {
+                        ready.push_back(k);
+                    }
+                }
+            }
+            if ndone == ops.len() {
+                break;
+            }
+
+            // only cycles are left: find one, and move one of its ops out of the way
+            while done[cursor] {
+                cursor += 1;
+            }
+            let mut k = cursor;
+            let mut seen = vec![false; ops.len()];
+            while !seen[k] {
+                seen[k] = true;
+                match pending.get(ops[k].to.as_path()) {
+                    Some(&x) => k = x,
+                    None => break,
+                }
+            }
+            if !pending.contains_key(ops[k].to.as_path()) {
+                // not part of a cycle, e.g. several ops share the same `to`
+                ready.push_back(k);
+                continue;
+            }
+            let tmp = Self::temp_name(&ops[k].from);
+            steps.push(RenameOp {
+                from: froms[k].clone(),
+                to: tmp.clone(),
+            });
+            froms[k] = tmp;
+            pending.remove(ops[k].from.as_path());
+            if let Some(&x) = waiting.get(ops[k].from.as_path()) {
+                ready.push_back(x);
+            }
+        }
+    }
+
+    /// A hidden, unused name next to `p`
+    pub(crate) fn temp_name(p: &Path) -> PathBuf {
+        let name = p
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        p.with_file_name(format!(".{}.renify-{}", name, &id[..8]))
+    }
+}
+
+impl<'a> IntoIterator for &'a RenamePlan {
+    type Item = &'a RenameOp;
+    type IntoIter = std::slice::Iter<'a, RenameOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(d: &Path, from: &str, to: &str) -> RenameOp {
+        RenameOp {
+            from: d.join(from),
+            to: d.join(to),
+        }
+    }
+
+    fn touch(d: &Path, names: &[&str]) {
+        for x in names.iter() {
+            std::fs::write(d.join(x), x).unwrap();
+        }
+    }
+
+    fn read(d: &Path, name: &str) -> String {
+        std::fs::read_to_string(d.join(name)).unwrap()
+    }
+
+    fn plan(ops: Vec<RenameOp>) -> RenamePlan {
+        RenamePlan {
+            ops,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["1", "2"]);
+        let steps = plan(vec![op(d, "1", "2"), op(d, "2", "3")]).steps();
+        assert_eq!(steps, vec![op(d, "2", "3"), op(d, "1", "2")]);
+        RenamePlan::check_steps(&steps).unwrap();
+        RenamePlan::apply_steps(&steps, |_, _| Ok(()), |_, _| Ok(())).unwrap();
+        assert_eq!(read(d, "2"), "1");
+        assert_eq!(read(d, "3"), "2");
+        assert!(!d.join("1").exists());
+    }
+
+    #[test]
+    fn interleaved() {
+        // ops of a folder apart in the plan, and a move across folders
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        for x in ["d", "e"] {
+            std::fs::create_dir(d.join(x)).unwrap();
+        }
+        touch(d, &["d/1", "d/2", "e/x", "e/y"]);
+        let steps = plan(vec![
+            op(d, "d/1", "d/2"),
+            op(d, "e/x", "d/1"),
+            op(d, "d/2", "d/3"),
+            op(d, "e/y", "e/x"),
+        ])
+        .steps();
+        RenamePlan::check_steps(&steps).unwrap();
+        RenamePlan::apply_steps(&steps, |_, _| Ok(()), |_, _| Ok(())).unwrap();
+        assert_eq!(read(d, "d/1"), "e/x");
+        assert_eq!(read(d, "d/2"), "d/1");
+        assert_eq!(read(d, "d/3"), "d/2");
+        assert_eq!(read(d, "e/x"), "e/y");
+        assert!(!d.join("e/y").exists());
+    }
+
+    #[test]
+    fn swap() {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["a", "b"]);
+        let steps = plan(vec![op(d, "a", "b"), op(d, "b", "a")]).steps();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(RenamePlan::compose(&steps).len(), 2);
+        RenamePlan::check_steps(&steps).unwrap();
+        RenamePlan::apply_steps(&steps, |_, _| Ok(()), |_, _| Ok(())).unwrap();
+        assert_eq!(read(d, "a"), "b");
+        assert_eq!(read(d, "b"), "a");
+        assert_eq!(std::fs::read_dir(d).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn shifted_start() {
+        // `--start 2` on files numbered from 1
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["1", "2", "3"]);
+        let steps = plan(vec![op(d, "1", "2"), op(d, "2", "3"), op(d, "3", "4")]).steps();
+        assert_eq!(
+            steps,
+            vec![op(d, "3", "4"), op(d, "2", "3"), op(d, "1", "2")]
+        );
+        RenamePlan::apply_steps(&steps, |_, _| Ok(()), |_, _| Ok(())).unwrap();
+        for (x, y) in [("2", "1"), ("3", "2"), ("4", "3")] {
+            assert_eq!(read(d, x), y);
+        }
+    }
+
+    #[test]
+    fn case_only() {
+        // a hard link stands for the same entry under another case
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["a"]);
+        std::fs::hard_link(d.join("a"), d.join("A")).unwrap();
+        let steps = plan(vec![op(d, "a", "A")]).steps();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].from, d.join("a"));
+        assert_eq!(steps[1].to, d.join("A"));
+        assert_eq!(steps[0].to, steps[1].from);
+    }
+
+    #[test]
+    fn rollback() {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["a", "b", "x"]);
+        let steps = vec![op(d, "a", "c"), op(d, "b", "x")];
+        assert!(RenamePlan::check_steps(&steps).is_err());
+        let err = RenamePlan::apply_steps(&steps, |_, _| Ok(()), |_, _| Ok(())).unwrap_err();
+        assert!(matches!(
+            err.root_cause().downcast_ref::<Error>(),
+            Some(Error::Conflict(_))
+        ));
+        assert_eq!(read(d, "a"), "a");
+        assert!(!d.join("c").exists());
+    }
+
+    #[test]
+    fn check_steps() {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        std::fs::create_dir(d.join("d")).unwrap();
+        touch(d, &["a", "d/x"]);
+
+        // contents first, then their folder
+        RenamePlan::check_steps(&[op(d, "d/x", "d/y"), op(d, "d", "e")]).unwrap();
+        // moved along with their folder
+        RenamePlan::check_steps(&[op(d, "d", "e"), op(d, "e/x", "e/y")]).unwrap();
+        assert!(RenamePlan::check_steps(&[op(d, "d", "e"), op(d, "d/x", "d/y")]).is_err());
+        // missing, or taken
+        assert!(RenamePlan::check_steps(&[op(d, "b", "c")]).is_err());
+        assert!(RenamePlan::check_steps(&[op(d, "a", "d")]).is_err());
+        assert!(RenamePlan::check_steps(&[op(d, "a", "b"), op(d, "a", "c")]).is_err());
+    }
+
+    #[test]
+    fn applied_prefix() {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        touch(d, &["1", "2"]);
+        let steps = plan(vec![op(d, "1", "2"), op(d, "2", "3")]).steps();
+        assert_eq!(RenamePlan::applied_prefix(&steps, 8), 0);
+        std::fs::rename(d.join("2"), d.join("3")).unwrap();
+        assert_eq!(RenamePlan::applied_prefix(&steps, 8), 1);
+        assert_eq!(RenamePlan::applied_prefix(&steps, 1), 1);
+        std::fs::rename(d.join("1"), d.join("2")).unwrap();
+        assert_eq!(RenamePlan::applied_prefix(&steps, 8), 2);
+    }
+}
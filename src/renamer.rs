@@ -0,0 +1,951 @@
+use anyhow::Result;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::filter::Filter;
+use crate::template::Tokens;
+use crate::{
+    CounterScope, Error, Method, Relink, RenameOp, RenamePlan, Sort, Target, Template, BIT_MAX,
+};
+
+/// Entries to be renamed: depth => parent directory => paths
+pub type Targets = BTreeMap<usize, BTreeMap<PathBuf, Vec<PathBuf>>>;
+
+/// Non-interactive renamer: fetches targets and plans the renaming.
+///
+/// ```no_run
+/// let renamer = renify::Renamer {
+///     method: renify::Method::Znum,
+///     nbits: 4,
+///     ..Default::default()
+/// };
+/// let plan = renamer.plan("images")?;
+/// plan.apply()?;
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Renamer {
+    /// Entries to rename
+    pub target: Target,
+
+    /// Method for renaming
+    pub method: Method,
+
+    /// Doing recursively or not
+    pub recursive: bool,
+
+    /// Depth when doing recursively
+    pub depth: Option<usize>,
+
+    /// The number of bit, for `Method::Random` & `Method::Znum`
+    pub nbits: usize,
+
+    /// Initial number, for `Method::Num` & `Method::Znum`
+    pub start: usize,
+
+    /// Text string for `Method::Prefix` & `Method::Append`
+    pub with: Option<String>,
+
+    /// Delimiter
+    pub delimiter: String,
+
+    /// Not preserving consistent file stems
+    pub indiscriminate: bool,
+
+    /// Regex pattern to search for, for `Method::Regex`
+    pub find: Option<String>,
+
+    /// Replacement for `Method::Regex`, `$1` & `${name}` refer to capture groups
+    pub replace: String,
+
+    /// Case-insensitive matching, for `Method::Regex`
+    pub ignore_case: bool,
+
+    /// Replace the first match only, for `Method::Regex`
+    pub replace_first: bool,
+
+    /// Match against the full file name instead of the file stem, for `Method::Regex`
+    pub full_name: bool,
+
+    /// Keep entries not matched as they are instead of failing, for `Method::Regex`
+    pub skip_unmatched: bool,
+
+    /// Naming template, for `Method::Template`, e.g. `{parent}_{n:04}_{stem|lower}.{ext}`
+    pub template: Option<String>,
+
+    /// Globs targets must match, on their name or path relative to the source
+    pub include: Vec<String>,
+
+    /// Globs of entries left out, folders matched are not descended into
+    pub exclude: Vec<String>,
+
+    /// Regexes targets must match, on their name or path relative to the source
+    pub include_regex: Vec<String>,
+
+    /// Regexes of entries left out, folders matched are not descended into
+    pub exclude_regex: Vec<String>,
+
+    /// Extensions targets must have, e.g. `jpg`, case-insensitive
+    pub ext: Vec<String>,
+
+    /// Smallest size of files, e.g. `10K`, `1.5M`, units are powers of 1024
+    pub min_size: Option<String>,
+
+    /// Largest size of files, e.g. `10K`, `1.5M`, units are powers of 1024
+    pub max_size: Option<String>,
+
+    /// Modified after this date, or within this duration, e.g. `2024-01-31`, `7d`, `12h`
+    pub newer_than: Option<String>,
+
+    /// Modified before this date, or longer ago than this duration, e.g. `2024-01-31`, `7d`
+    pub older_than: Option<String>,
+
+    /// Empty files, or empty folders, only
+    pub empty: bool,
+
+    /// Including hidden entries: dot-files, dot-folders & those with a hidden attribute
+    pub hidden: bool,
+
+    /// History directory, never renamed, `History::default_dir()` if not set
+    pub history_dir: Option<PathBuf>,
+
+    /// Skipping entries ignored by `.gitignore`, `.ignore` & `.renifyignore` files
+    pub respect_ignore: bool,
+
+    /// Pointing links under the source at the new names of their destinations
+    pub retarget: bool,
+
+    /// Order entries of a folder are numbered in, see `Renamer::order()` if not set
+    pub sort: Option<Sort>,
+
+    /// Comparing names case-insensitively when sorting
+    pub sort_ignore_case: bool,
+
+    /// Reversing that order
+    pub reverse: bool,
+
+    /// Entries sharing a counter, for `Method::Num`, `Method::Znum` & `{n}`
+    pub counter_scope: CounterScope,
+}
+
+impl Default for Renamer {
+    fn default() -> Self {
+        Self {
+            target: Target::File,
+            method: Method::Num,
+            recursive: false,
+            depth: None,
+            nbits: 3,
+            start: 1,
+            with: None,
+            delimiter: "-".to_string(),
+            indiscriminate: false,
+            find: None,
+            replace: String::new(),
+            ignore_case: false,
+            replace_first: false,
+            full_name: false,
+            skip_unmatched: false,
+            template: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_regex: Vec::new(),
+            exclude_regex: Vec::new(),
+            ext: Vec::new(),
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            empty: false,
+            hidden: false,
+            history_dir: None,
+            respect_ignore: false,
+            retarget: false,
+            sort: None,
+            sort_ignore_case: false,
+            reverse: false,
+            counter_scope: CounterScope::PerDir,
+        }
+    }
+}
+
+impl Renamer {
+    /// Fetch targets and compute every old → new pair, without touching the filesystem.
+    pub fn plan<P: AsRef<Path>>(&self, source: P) -> Result<RenamePlan> {
+        let mut ys = self.fetch_targets(&source)?;
+        self.order(&mut ys);
+        let mut plan = self.plan_targets(&ys)?;
+        if self.retarget {
+            plan.relinks = self.relinks(source, &plan)?;
+        }
+        Ok(plan)
+    }
+
+    /// Links under `source`, symlinks given as the source included, whose destination, or
+    /// themselves, are moved by `plan` so that they would no longer point at the same entry:
+    /// how to point them at the new names. Relative links are kept relative.
+    pub fn relinks<P: AsRef<Path>>(&self, source: P, plan: &RenamePlan) -> Result<Vec<Relink>> {
+        if plan.is_empty() {
+            return Ok(Vec::new());
+        }
+        let source = crate::canonicalize_nofollow(source.as_ref())?;
+        let filter = Filter::new(self)?;
+        let links: Vec<PathBuf> = match source.is_symlink() {
+            true => vec![source.clone()],
+            false => self
+                .walk(&source, &filter)
+                .flatten()
+                .filter(|x| x.path_is_symlink())
+                .map(|x| x.into_path())
+                .collect(),
+        };
+        let mut ys = Vec::new();
+        for link in links.into_iter() {
+            let (parent, content) = match (link.parent(), std::fs::read_link(&link)) {
+                (Some(parent), Ok(content)) => (parent, content),
+                _ => continue,
+            };
+            let dest = crate::normalize(&parent.join(&content));
+            let dest_new = plan.map(&dest);
+            let link_new = plan.map(&link);
+            let content_new = match (content.is_absolute(), link_new.parent()) {
+                (true, _) => dest_new.clone(),
+                (false, Some(parent_new)) => crate::relative_path(&dest_new, parent_new),
+                (false, None) => continue,
+            };
+            // where it would point, left as is
+            let now = match content.is_absolute() {
+                true => content.clone(),
+                false => crate::normalize(&link_new.parent().unwrap_or(parent).join(&content)),
+            };
+            if now != dest_new {
+                ys.push(Relink {
+                    link: link_new,
+                    from: content,
+                    to: content_new,
+                });
+            }
+        }
+        Ok(ys)
+    }
+
+    /// Compute every old → new pair for targets fetched beforehand.
+    /// Pairs whose name stays unchanged are left out.
+    pub fn plan_targets(&self, ys: &Targets) -> Result<RenamePlan> {
+        self.validate()?;
+        let mut state = State {
+            sources: ys
+                .values()
+                .flat_map(|x| x.values().flatten().cloned())
+                .collect(),
+            regex: match (&self.method, &self.find) {
+                (Method::Regex, Some(find)) => Some(
+                    RegexBuilder::new(find)
+                        .case_insensitive(self.ignore_case)
+                        .build()
+                        .map_err(|err| Error::BadInput(err.to_string()))?,
+                ),
+                _ => None,
+            },
+            template: match (&self.method, &self.template) {
+                (Method::Template, Some(template)) => Some(Template::parse(template)?),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        // named folder after folder, parents first, for counters shared across folders
+        let mut folders: Vec<(usize, &PathBuf, &Vec<PathBuf>)> = ys
+            .iter()
+            .flat_map(|(depth, paths)| paths.iter().map(|(pd, pfs)| (*depth, pd, pfs)))
+            .collect();
+        folders.sort_by(|a, b| self.compare_folders(a.1, b.1));
+        let mut names: HashMap<&PathBuf, PathBuf> = HashMap::new();
+        for (depth, pd, pfs) in folders.into_iter() {
+            for pf in pfs.iter() {
+                let path_new = self.gen_uniq(pf, pd, depth, &mut state)?;
+                if state.occupied.contains(&path_new) {
+                    anyhow::bail!(Error::Conflict(format!(
+                        "Conflict: {} => {} is planned for another entry.",
+                        pf.display(),
+                        path_new.display()
+                    )));
+                }
+                if &path_new != pf {
+                    state.occupied.insert(path_new.clone());
+                    names.insert(pf, path_new);
+                }
+            }
+        }
+
+        // renamed deepest first, so that folders are moved after what they hold
+        let mut plan = RenamePlan::default();
+        for (_, paths) in ys.iter().rev() {
+            for (_, pfs) in paths.iter() {
+                for pf in pfs.iter() {
+                    if let Some(path_new) = names.remove(pf) {
+                        plan.ops.push(RenameOp {
+                            from: pf.clone(),
+                            to: path_new,
+                        });
+                    }
+                }
+            }
+        }
+
+        // names of other sources are only free if those sources are renamed as well
+        let moving: HashSet<&PathBuf> = plan.iter().map(|op| &op.from).collect();
+        for op in plan.iter() {
+            if !moving.contains(&op.to)
+                && crate::exists_nofollow(&op.to)
+                && !crate::is_same_entry(&op.from, &op.to)
+            {
+                anyhow::bail!(Error::Conflict(format!(
+                    "Conflict: {} => {} already exists.",
+                    op.from.display(),
+                    op.to.display()
+                )));
+            }
+        }
+        Ok(plan)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.nbits > BIT_MAX {
+            anyhow::bail!(Error::BadInput(format!(
+                "The number of bits should be at most {BIT_MAX}."
+            )))
+        }
+        match self.method {
+            Method::Prefix | Method::Append if self.with.is_none() => {
+                anyhow::bail!(Error::BadInput(format!(
+                    "You should set the text by `--with` when using `Method::{:?}`.",
+                    self.method
+                )))
+            }
+            Method::Random if self.nbits == 0 => {
+                anyhow::bail!(Error::BadInput(
+                    "The number of bits should be greater than 0.".into()
+                ))
+            }
+            Method::Num | Method::Znum if self.start == 0 => {
+                anyhow::bail!(Error::BadInput(
+                    "Initial number should be greater than 0.".into()
+                ))
+            }
+            Method::Regex if self.find.is_none() => {
+                anyhow::bail!(Error::BadInput(
+                    "You should set the pattern by `--find` when using `Method::Regex`.".into()
+                ))
+            }
+            Method::Template => match &self.template {
+                None => anyhow::bail!(Error::BadInput(
+                    "You should set the template by `--template` when using `Method::Template`."
+                        .into()
+                )),
+                Some(template) => Template::parse(template).map(|_| ()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Entries to rename under `source`, in walk order: see `Renamer::order()`
+    pub fn fetch_targets<P: AsRef<Path>>(&self, source: P) -> Result<Targets> {
+        let source = crate::canonicalize_nofollow(source.as_ref())?;
+        let filter = Filter::new(self)?;
+        let ys = if source.is_symlink() || source.is_file() {
+            let name = Path::new(source.file_name().unwrap_or_default());
+            if filter.excluded(name) || !filter.included(name) || !filter.kept(&source) {
+                return Ok(BTreeMap::new());
+            }
+            let mut ys = BTreeMap::new();
+            let mut y = BTreeMap::new();
+            let parent = match source.parent() {
+                None => anyhow::bail!(Error::BadInput(
+                    "You can not reach the parent of root directory.".into()
+                )),
+                Some(p) => p.to_path_buf(),
+            };
+            y.insert(parent, vec![source.to_path_buf()]);
+            ys.insert(0_usize, y);
+            ys
+        } else {
+            let mut ys: Targets = BTreeMap::new();
+            for entry in self.walk(&source, &filter) {
+                match entry {
+                    Ok(entry) => {
+                        // skip root dir
+                        if entry.file_type().is_dir() && entry.depth() == 0 {
+                            continue;
+                        }
+
+                        // non-recrusive
+                        if !self.recursive && entry.depth() > 1 {
+                            continue;
+                        }
+
+                        // depth
+                        if let Some(d) = self.depth {
+                            if entry.depth() > d {
+                                continue;
+                            }
+                        }
+
+                        // filter, links are not followed
+                        if entry.path_is_symlink() {
+                            if let Target::Symlink = self.target {
+                            } else {
+                                continue;
+                            }
+                        } else if entry.file_type().is_file() {
+                            if let Target::File | Target::All = self.target {
+                            } else {
+                                continue;
+                            }
+                        } else if entry.file_type().is_dir() {
+                            if let Target::Dir | Target::All = self.target {
+                            } else {
+                                continue;
+                            }
+                        } else {
+                            continue;
+                        }
+
+                        // history, or its parents
+                        if filter.holds_history(entry.path()) {
+                            continue;
+                        }
+
+                        // include & ext
+                        if !filter.included(Self::relative(entry.path(), &source)) {
+                            continue;
+                        }
+
+                        // size, time & emptiness
+                        if !filter.kept(entry.path()) {
+                            continue;
+                        }
+
+                        // save
+                        let parent = match entry.path().parent() {
+                            None => continue,
+                            Some(p) => p.to_path_buf(),
+                        };
+                        ys.entry(entry.depth())
+                            .or_default()
+                            .entry(parent)
+                            .or_default()
+                            .push(entry.path().to_path_buf());
+                    }
+                    Err(err) => anyhow::bail!(Error::Io(format!(
+                        "Failed to read {}: {}",
+                        err.path().unwrap_or(&source).display(),
+                        err.io_error().map_or(err.to_string(), |x| x.to_string())
+                    ))),
+                }
+            }
+            ys
+        };
+        Ok(ys)
+    }
+
+    /// Sort the entries of each folder by `sort`, natural order by default for
+    /// `Method::Num` & `Method::Znum`, names that are numbers first for others
+    pub fn order(&self, ys: &mut Targets) {
+        let sort = self.sort.or(match self.method {
+            Method::Num | Method::Znum => Some(Sort::Natural),
+            _ => None,
+        });
+        for (_, paths) in ys.iter_mut() {
+            for (_, path) in paths.iter_mut() {
+                match sort {
+                    Some(sort) => sort.sort(path, self.sort_ignore_case),
+                    None => path.sort_by_key(|x| {
+                        x.file_stem()
+                            .and_then(|x| x.to_str())
+                            .and_then(|x| x.parse::<usize>().ok())
+                            .unwrap_or(usize::MAX)
+                    }),
+                }
+                if self.reverse {
+                    path.reverse();
+                }
+            }
+        }
+    }
+
+    /// Folders in natural order of their paths, a folder before those it holds
+    fn compare_folders(&self, a: &Path, b: &Path) -> std::cmp::Ordering {
+        let (mut xs, mut ys) = (a.components(), b.components());
+        loop {
+            match (xs.next(), ys.next()) {
+                (None, None) => return std::cmp::Ordering::Equal,
+                (None, Some(_)) => return std::cmp::Ordering::Less,
+                (Some(_), None) => return std::cmp::Ordering::Greater,
+                (Some(x), Some(y)) => {
+                    let ord = crate::natural(
+                        &x.as_os_str().to_string_lossy(),
+                        &y.as_os_str().to_string_lossy(),
+                        self.sort_ignore_case,
+                    );
+                    if ord != std::cmp::Ordering::Equal {
+                        return ord;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Next number of the counter of entries in the folder `pd` at `depth`
+    fn count(&self, pd: &Path, depth: usize, counters: &mut Counters) -> usize {
+        let count = counters
+            .entry(self.counter_scope.key(pd, depth))
+            .or_insert(self.start - 1);
+        *count += 1;
+        *count
+    }
+
+    fn gen_uniq(&self, pf: &Path, pd: &Path, depth: usize, state: &mut State) -> Result<PathBuf> {
+        // Generate unique file stem
+        let consistent = !self.indiscriminate
+            && self.is_file(pf)
+            && match self.method {
+                Method::Regex => !self.full_name,
+                Method::Template => false,
+                _ => true,
+            };
+        if consistent {
+            let path_wo_ext = pf.with_extension("");
+            if let Some(stem) = state.map_pf_stem.get(&path_wo_ext) {
+                return Ok(Self::with_stem(pf, stem));
+            }
+        }
+
+        loop {
+            let stem = match self.method {
+                Method::Time => chrono::Local::now()
+                    .format(&format!(
+                        "%Y{}%m{}%d{}%H{}%M{}%S{}%f",
+                        self.delimiter,
+                        self.delimiter,
+                        self.delimiter,
+                        self.delimiter,
+                        self.delimiter,
+                        self.delimiter
+                    ))
+                    .to_string(),
+                Method::Uuid => Uuid::new_v4().to_string(),
+                Method::Prefix => {
+                    format!(
+                        "{}{}{}",
+                        self.with.as_deref().unwrap_or_default(),
+                        self.delimiter,
+                        Self::file_stem(pf)?
+                    )
+                }
+                Method::Append => {
+                    format!(
+                        "{}{}{}",
+                        Self::file_stem(pf)?,
+                        self.delimiter,
+                        self.with.as_deref().unwrap_or_default()
+                    )
+                }
+                Method::Num => self.count(pd, depth, &mut state.counters).to_string(),
+                Method::Znum => format!(
+                    "{:0>1$}",
+                    self.count(pd, depth, &mut state.counters),
+                    self.nbits
+                ),
+                Method::Uppercase => Self::file_stem(pf)?.to_uppercase(),
+                Method::Lowercase => Self::file_stem(pf)?.to_lowercase(),
+                Method::Title => crate::case::title(Self::file_stem(pf)?),
+                Method::Snake => crate::case::snake(Self::file_stem(pf)?),
+                Method::Kebab => crate::case::kebab(Self::file_stem(pf)?),
+                Method::Camel => crate::case::camel(Self::file_stem(pf)?),
+                Method::Regex => {
+                    let re = match &state.regex {
+                        None => anyhow::bail!(Error::BadInput(
+                            "No pattern specified by `--find`.".into()
+                        )),
+                        Some(re) => re,
+                    };
+                    let haystack = match self.full_name {
+                        true => Self::file_name(pf)?,
+                        false => Self::file_stem(pf)?,
+                    };
+                    if !re.is_match(haystack) {
+                        if self.skip_unmatched {
+                            return Ok(pf.to_path_buf());
+                        }
+                        anyhow::bail!(Error::NothingMatched(format!(
+                            "Not matched by `--find {}`: {}",
+                            re,
+                            pf.display()
+                        )));
+                    }
+                    let replace = self.replace.as_str();
+                    let y = match self.replace_first {
+                        true => re.replace(haystack, replace),
+                        false => re.replace_all(haystack, replace),
+                    };
+                    if y.is_empty() || y.contains(['/', '\\']) {
+                        anyhow::bail!(Error::BadInput(format!(
+                            "Invalid name replaced: {} => {:?}",
+                            pf.display(),
+                            y
+                        )));
+                    }
+                    if self.full_name {
+                        let p_new = pf.with_file_name(y.as_ref());
+                        if state.is_free(pf, &p_new) {
+                            break Ok(p_new);
+                        }
+                        anyhow::bail!(Error::Conflict(format!(
+                            "Conflict: {} => {} already exists.",
+                            pf.display(),
+                            p_new.display()
+                        )));
+                    }
+                    y.to_string()
+                }
+                Method::Template => {
+                    let template = match &state.template {
+                        None => anyhow::bail!(Error::BadInput(
+                            "No template specified by `--template`.".into()
+                        )),
+                        Some(template) => template,
+                    };
+
+                    // files with the same stem share the same tokens
+                    let share = !self.indiscriminate && self.is_file(pf);
+                    let path_wo_ext = pf.with_extension("");
+                    let (tokens, shared) = match state.map_pf_tokens.get(&path_wo_ext) {
+                        Some(tokens) if share => (tokens.clone(), true),
+                        _ => {
+                            let n = match template.uses_counter() {
+                                false => 0,
+                                true => self.count(pd, depth, &mut state.counters),
+                            };
+                            let tokens = Tokens {
+                                n,
+                                random: Self::random(template.random_len(self.nbits)),
+                                uuid: Uuid::new_v4().to_string(),
+                            };
+                            (tokens, false)
+                        }
+                    };
+                    let p_new = pf.with_file_name(template.render(pf, &tokens, self.nbits)?);
+                    if share {
+                        state.map_pf_tokens.insert(path_wo_ext, tokens);
+                    }
+                    if state.is_free(pf, &p_new) {
+                        break Ok(p_new);
+                    }
+                    if shared || !template.is_varying() {
+                        anyhow::bail!(Error::Conflict(format!(
+                            "Conflict: {} => {} already exists.",
+                            pf.display(),
+                            p_new.display()
+                        )));
+                    }
+                    continue;
+                }
+                Method::Random => Self::random(self.nbits),
+            };
+
+            // nothing left, e.g. `___` in snake case
+            if stem.is_empty() {
+                anyhow::bail!(Error::BadInput(format!(
+                    "Invalid name: {} => empty stem with `Method::{:?}`",
+                    pf.display(),
+                    self.method
+                )));
+            }
+
+            // check if new stem file exists
+            let p_new = Self::with_stem(pf, &stem);
+
+            // save if keep consistance
+            if consistent {
+                state.map_pf_stem.insert(pf.with_extension(""), stem);
+            }
+
+            if state.is_free(pf, &p_new) {
+                break Ok(p_new);
+            }
+
+            // generating again makes no difference
+            if let Method::Prefix
+            | Method::Append
+            | Method::Uppercase
+            | Method::Lowercase
+            | Method::Title
+            | Method::Snake
+            | Method::Kebab
+            | Method::Camel
+            | Method::Regex = self.method
+            {
+                anyhow::bail!(Error::Conflict(format!(
+                    "Conflict: {} => {} already exists.",
+                    pf.display(),
+                    p_new.display()
+                )));
+            }
+        }
+    }
+
+    /// Whether `pf` is a file target, whose stem may be shared with other files
+    fn is_file(&self, pf: &Path) -> bool {
+        match self.target {
+            Target::File => true,
+            Target::All => !pf.is_dir(),
+            _ => false,
+        }
+    }
+
+    /// ASCII letters and numbers: a-z, A-Z and 0-9
+    fn random(n: usize) -> String {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(n)
+            .map(char::from)
+            .collect()
+    }
+
+    /// `dir/x.jpg` with stem `y` => `dir/y.jpg`
+    fn with_stem(p: &Path, stem: &str) -> PathBuf {
+        match p.extension() {
+            None => p.with_file_name(stem),
+            Some(ext) => {
+                let mut name = std::ffi::OsString::from(stem);
+                name.push(".");
+                name.push(ext);
+                p.with_file_name(name)
+            }
+        }
+    }
+
+    fn file_name(p: &Path) -> Result<&str> {
+        match p.file_name().and_then(|x| x.to_str()) {
+            Some(x) => Ok(x),
+            None => anyhow::bail!(Error::BadInput(format!(
+                "File name is not valid UTF-8: {}",
+                p.display()
+            ))),
+        }
+    }
+
+    fn file_stem(p: &Path) -> Result<&str> {
+        match p.file_stem().and_then(|x| x.to_str()) {
+            Some(x) => Ok(x),
+            None => anyhow::bail!(Error::BadInput(format!(
+                "File stem is not valid UTF-8: {}",
+                p.display()
+            ))),
+        }
+    }
+
+    /// Dot-files & dot-folders, or with the hidden attribute on Windows, or the hidden flag on macOS
+    fn is_hidden(entry: &DirEntry) -> bool {
+        if entry
+            .file_name()
+            .to_str()
+            .map(|p| p.starts_with('.'))
+            .unwrap_or(false)
+        {
+            return true;
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+            if let Ok(x) = entry.metadata() {
+                return x.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+            }
+        }
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::macos::fs::MetadataExt;
+            const UF_HIDDEN: u32 = 0x8000;
+            if let Ok(x) = entry.metadata() {
+                return x.st_flags() & UF_HIDDEN != 0;
+            }
+        }
+        false
+    }
+
+    /// Entries under `source`, hidden, excluded & ignored ones & the history left out along with their contents
+    fn walk<'a>(
+        &self,
+        source: &'a Path,
+        filter: &'a Filter,
+    ) -> impl Iterator<Item = walkdir::Result<DirEntry>> + 'a {
+        let hidden = self.hidden;
+        WalkDir::new(source).into_iter().filter_entry(move |x| {
+            x.depth() == 0
+                || !((!hidden && Self::is_hidden(x))
+                    || filter.is_history(x.path())
+                    || filter.ignored(x.path(), x.file_type().is_dir())
+                    || filter.excluded(Self::relative(x.path(), source)))
+        })
+    }
+
+    /// `p` relative to `source`
+    fn relative<'a>(p: &'a Path, source: &Path) -> &'a Path {
+        p.strip_prefix(source).unwrap_or(p)
+    }
+
+    /// The max depth of the targets under `source`.
+    pub fn max_depth<P: AsRef<Path>>(&self, source: P) -> usize {
+        let source = source.as_ref();
+        let source = source.canonicalize().unwrap_or(source.to_path_buf());
+        let source = source.as_path();
+        let filter = Filter::new(self).unwrap_or_default();
+        let mut depth = 0usize;
+        for entry in self.walk(source, &filter) {
+            match entry {
+                Ok(entry) => {
+                    if entry.path_is_symlink() {
+                        if let Target::Symlink = self.target {
+                            depth = entry.depth().max(depth);
+                        }
+                    } else if entry.file_type().is_dir() {
+                        if let Target::Dir | Target::All = self.target {
+                            depth = entry.depth().max(depth);
+                        } else {
+                            continue;
+                        }
+                    } else if entry.file_type().is_file() {
+                        if let Target::File | Target::All = self.target {
+                            depth = entry.depth().max(depth);
+                        } else {
+                            continue;
+                        }
+                    }
+                }
+                Err(_) => continue,
+            }
+        }
+        depth
+    }
+}
+
+/// Counters, keyed by `CounterScope::key()`
+type Counters = HashMap<(usize, PathBuf), usize>;
+
+/// Bookkeeping while planning
+#[derive(Default)]
+struct State {
+    /// All entries to be renamed
+    sources: HashSet<PathBuf>,
+
+    /// Names planned so far
+    occupied: HashSet<PathBuf>,
+
+    /// Numbers given so far
+    counters: Counters,
+
+    /// New stem of files sharing the same stem
+    map_pf_stem: HashMap<PathBuf, String>,
+
+    /// Compiled `--find`
+    regex: Option<Regex>,
+
+    /// Parsed `--template`
+    template: Option<Template>,
+
+    /// Tokens of files sharing the same stem, for `Method::Template`
+    map_pf_tokens: HashMap<PathBuf, Tokens>,
+}
+
+impl State {
+    /// Names planned for other files are taken, names of sources will be freed
+    fn is_free(&self, pf: &Path, p_new: &Path) -> bool {
+        !self.occupied.contains(p_new)
+            && (self.sources.contains(p_new)
+                || !crate::exists_nofollow(p_new)
+                || crate::is_same_entry(pf, p_new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// New names of the files of a small tree, by their path relative to it
+    fn numbers(scope: CounterScope) -> Vec<(String, String)> {
+        let d = tempfile::tempdir().unwrap();
+        let d = d.path();
+        for x in ["sub2/deep", "sub10"] {
+            std::fs::create_dir_all(d.join(x)).unwrap();
+        }
+        for x in [
+            "a.txt",
+            "b.txt",
+            "sub2/c.txt",
+            "sub2/deep/f.txt",
+            "sub10/e.txt",
+        ] {
+            std::fs::write(d.join(x), x).unwrap();
+        }
+        let renamer = Renamer {
+            recursive: true,
+            counter_scope: scope,
+            ..Default::default()
+        };
+        let plan = renamer.plan(d).unwrap();
+        let mut ys: Vec<(String, String)> = plan
+            .iter()
+            .map(|op| {
+                let from = op.from.strip_prefix(d).unwrap();
+                let to = op.to.file_name().unwrap();
+                (from.display().to_string(), to.to_string_lossy().into())
+            })
+            .collect();
+        ys.sort();
+        ys
+    }
+
+    fn pairs(xs: &[(&str, &str)]) -> Vec<(String, String)> {
+        xs.iter().map(|&(a, b)| (a.into(), b.into())).collect()
+    }
+
+    #[test]
+    fn counter_scopes() {
+        // parents first, subfolders in natural order
+        assert_eq!(
+            numbers(CounterScope::Global),
+            pairs(&[
+                ("a.txt", "1.txt"),
+                ("b.txt", "2.txt"),
+                ("sub10/e.txt", "5.txt"),
+                ("sub2/c.txt", "3.txt"),
+                ("sub2/deep/f.txt", "4.txt"),
+            ])
+        );
+        assert_eq!(
+            numbers(CounterScope::PerDir),
+            pairs(&[
+                ("a.txt", "1.txt"),
+                ("b.txt", "2.txt"),
+                ("sub10/e.txt", "1.txt"),
+                ("sub2/c.txt", "1.txt"),
+                ("sub2/deep/f.txt", "1.txt"),
+            ])
+        );
+        assert_eq!(
+            numbers(CounterScope::PerDepth),
+            pairs(&[
+                ("a.txt", "1.txt"),
+                ("b.txt", "2.txt"),
+                ("sub10/e.txt", "2.txt"),
+                ("sub2/c.txt", "1.txt"),
+                ("sub2/deep/f.txt", "1.txt"),
+            ])
+        );
+    }
+}
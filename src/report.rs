@@ -0,0 +1,156 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::{Relink, RenameOp, RenamePlan, Renamer};
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Colored logs & progress bars
+    #[default]
+    Text,
+    /// One JSON report per line on stdout, nothing else
+    Json,
+}
+
+/// What became of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// Not touched, e.g. a dry run or a run interrupted before it
+    Pending,
+    /// Renamed, or renamed back for an undo
+    Renamed,
+    /// Renamed, then reverted because another one failed
+    RolledBack,
+    /// The one that could not be renamed
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub status: FileStatus,
+
+    /// Why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of a rename, undo or redo, printed with `--output json`
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// `rename`, `undo` or `redo`
+    pub task: String,
+
+    /// Folder renamed
+    pub source: Option<PathBuf>,
+
+    /// Options resolved, for `rename`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Renamer>,
+
+    /// History session id, none for dry runs
+    pub session: Option<String>,
+
+    pub dry_run: bool,
+
+    /// Files renamed
+    pub done: usize,
+
+    /// Files planned
+    pub total: usize,
+
+    pub files: Vec<FileReport>,
+
+    /// Links pointed at new names, with `--retarget`
+    pub relinks: Vec<Relink>,
+
+    /// Links left pointing at names moved, without `--retarget`
+    pub dangling: Vec<PathBuf>,
+
+    /// Why the run failed or stopped
+    pub error: Option<String>,
+}
+
+impl Report {
+    /// All files of `plan` pending
+    pub fn new(task: &str, plan: &RenamePlan) -> Self {
+        Self {
+            task: task.to_string(),
+            source: None,
+            options: None,
+            session: None,
+            dry_run: false,
+            done: 0,
+            total: plan.len(),
+            files: plan
+                .iter()
+                .map(|op| FileReport {
+                    from: op.from.clone(),
+                    to: op.to.clone(),
+                    status: FileStatus::Pending,
+                    error: None,
+                })
+                .collect(),
+            relinks: plan.relinks.clone(),
+            dangling: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// File statuses once `steps` were run: the first `applied` of them are in effect,
+    /// `reached` of them were done before a failure, `result` is what the run gave.
+    pub fn settle(
+        &mut self,
+        steps: &[RenameOp],
+        applied: usize,
+        reached: usize,
+        result: &Result<usize>,
+    ) {
+        let renamed = RenamePlan::compose(&steps[..applied]);
+        let touched = RenamePlan::compose(&steps[..reached]);
+        let renamed: HashSet<&Path> = renamed.iter().map(|op| op.from.as_path()).collect();
+        let touched: HashSet<&Path> = touched.iter().map(|op| op.from.as_path()).collect();
+        for x in self.files.iter_mut() {
+            x.status = if renamed.contains(x.from.as_path()) {
+                FileStatus::Renamed
+            } else if touched.contains(x.from.as_path()) {
+                FileStatus::RolledBack
+            } else {
+                FileStatus::Pending
+            };
+        }
+        match result {
+            Err(err) => {
+                if let Some(step) = steps.get(reached) {
+                    if let Some(x) = self
+                        .files
+                        .iter_mut()
+                        .find(|x| x.from == step.from || x.to == step.to)
+                    {
+                        x.status = FileStatus::Failed;
+                        x.error = Some(err.root_cause().to_string());
+                    }
+                }
+                self.error = Some(format!("{:#}", err));
+            }
+            Ok(n) if *n < steps.len() => self.error = Some("Interrupted".to_string()),
+            Ok(_) => {}
+        }
+        self.done = self
+            .files
+            .iter()
+            .filter(|x| x.status == FileStatus::Renamed)
+            .count();
+    }
+
+    /// Print it as one line of JSON
+    pub fn print(&self) -> Result<()> {
+        println!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Order of the entries of a folder, which is the order they are numbered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// => a10.jpg, a2.jpg, b.jpg (code points)
+    Name,
+    /// => a2.jpg, a10.jpg, b.jpg (numbers in names compared by value)
+    Natural,
+    /// Modification time, oldest first
+    Mtime,
+    /// Creation time, oldest first, the modification time where not supported
+    Ctime,
+    /// Size, smallest first
+    Size,
+    /// Extension, then name
+    Ext,
+    /// Shuffled
+    Random,
+    /// As listed by the filesystem
+    None,
+}
+
+impl Sort {
+    /// Name used by `--sort`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Natural => "natural",
+            Self::Mtime => "mtime",
+            Self::Ctime => "ctime",
+            Self::Size => "size",
+            Self::Ext => "ext",
+            Self::Random => "random",
+            Self::None => "none",
+        }
+    }
+
+    /// Sort `paths` in place, ties broken by name, names compared case-insensitively if `ignore_case`
+    pub fn sort(&self, paths: &mut [PathBuf], ignore_case: bool) {
+        let by_name = |a: &PathBuf, b: &PathBuf| {
+            let (a, b) = (Self::name_of(a), Self::name_of(b));
+            match ignore_case {
+                true => a
+                    .to_lowercase()
+                    .cmp(&b.to_lowercase())
+                    .then_with(|| a.cmp(&b)),
+                false => a.cmp(&b),
+            }
+        };
+        if let Self::Mtime | Self::Ctime | Self::Size | Self::Ext = self {
+            // the sorts below are stable
+            paths.sort_by(by_name);
+        }
+        match self {
+            Self::Name => paths.sort_by(by_name),
+            Self::Natural => {
+                paths.sort_by(|a, b| natural(&Self::name_of(a), &Self::name_of(b), ignore_case))
+            }
+            Self::Mtime => {
+                paths.sort_by_cached_key(|x| {
+                    x.symlink_metadata()
+                        .and_then(|x| x.modified())
+                        .unwrap_or(SystemTime::UNIX_EPOCH)
+                });
+            }
+            Self::Ctime => {
+                paths.sort_by_cached_key(|x| {
+                    x.symlink_metadata()
+                        .and_then(|x| x.created().or_else(|_| x.modified()))
+                        .unwrap_or(SystemTime::UNIX_EPOCH)
+                });
+            }
+            Self::Size => {
+                paths.sort_by_cached_key(|x| x.symlink_metadata().map(|x| x.len()).unwrap_or(0));
+            }
+            Self::Ext => {
+                paths.sort_by_cached_key(|x| {
+                    x.extension()
+                        .map(|x| x.to_string_lossy().to_lowercase())
+                        .unwrap_or_default()
+                });
+            }
+            Self::Random => paths.shuffle(&mut rand::thread_rng()),
+            Self::None => {}
+        }
+    }
+
+    fn name_of(p: &Path) -> std::borrow::Cow<'_, str> {
+        p.file_name().unwrap_or_default().to_string_lossy()
+    }
+}
+
+/// Natural order: runs of digits compared by their value, e.g. `img2` < `img10`, digits of
+/// any script, e.g. `٣` or `３`, counting as such. Equal values compare by their number of
+/// leading zeros, fewer first. With `ignore_case`, letters are compared case-folded, case
+/// deciding only between names otherwise equal.
+pub fn natural(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    match ignore_case {
+        true => compare(&a.to_lowercase(), &b.to_lowercase()).then_with(|| compare(a, b)),
+        false => compare(a, b),
+    }
+}
+
+fn compare(a: &str, b: &str) -> Ordering {
+    let (mut xs, mut ys) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (xs.peek(), ys.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&x), Some(&y)) if digit(x).is_some() && digit(y).is_some() => {
+                let x = digits(&mut xs);
+                let y = digits(&mut ys);
+                let (x0, y0) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ord = x0
+                    .len()
+                    .cmp(&y0.len())
+                    .then_with(|| x0.cmp(y0))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.cmp(y);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                xs.next();
+                ys.next();
+            }
+        }
+    }
+}
+
+/// The run of digits `xs` starts with, as ASCII digits
+fn digits<I: Iterator<Item = char>>(xs: &mut std::iter::Peekable<I>) -> String {
+    let mut s = String::new();
+    while let Some(x) = xs.peek().and_then(|&x| digit(x)) {
+        s.push(char::from(b'0' + x as u8));
+        xs.next();
+    }
+    s
+}
+
+/// Value of a decimal digit: ASCII, fullwidth, or of the scripts in common use
+fn digit(c: char) -> Option<u32> {
+    if let Some(x) = c.to_digit(10) {
+        return Some(x);
+    }
+    if !c.is_numeric() {
+        return None;
+    }
+    const ZEROS: [u32; 21] = [
+        0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6,
+        0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0, 0x1810, 0xFF10,
+    ];
+    let c = c as u32;
+    ZEROS
+        .iter()
+        .find(|&&zero| (zero..zero + 10).contains(&c))
+        .map(|zero| c - zero)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_by_value() {
+        assert_eq!(natural("img2", "img10", false), Ordering::Less);
+        assert_eq!(natural("img10", "img2", false), Ordering::Greater);
+        assert_eq!(natural("a1b2", "a1b10", false), Ordering::Less);
+        assert_eq!(natural("9", "10", false), Ordering::Less);
+        assert_eq!(natural("img", "img1", false), Ordering::Less);
+        assert_eq!(natural("img2", "img2", false), Ordering::Equal);
+        assert_eq!(
+            natural(
+                "x99999999999999999999999",
+                "x100000000000000000000000",
+                false
+            ),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(natural("img2", "img02", false), Ordering::Less);
+        assert_eq!(natural("img02", "img002", false), Ordering::Less);
+        assert_eq!(natural("img02", "img10", false), Ordering::Less);
+        assert_eq!(natural("img002", "img1", false), Ordering::Greater);
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert_eq!(natural("IMG1", "img2", false), Ordering::Less);
+        assert_eq!(natural("b", "A", false), Ordering::Greater);
+        assert_eq!(natural("b", "A", true), Ordering::Greater);
+        assert_eq!(natural("a", "B", true), Ordering::Less);
+        assert_eq!(natural("img2", "Img10", true), Ordering::Less);
+        // case only decides between names otherwise equal
+        assert_eq!(natural("IMG", "img", true), Ordering::Less);
+        assert_eq!(natural("Img", "img", true), natural("Img", "img", false));
+    }
+
+    #[test]
+    fn unicode_digits() {
+        // Arabic-Indic & fullwidth digits
+        assert_eq!(natural("img٣", "img10", false), Ordering::Less);
+        assert_eq!(natural("img٣", "img3", false), Ordering::Equal);
+        assert_eq!(natural("img１０", "img9", false), Ordering::Greater);
+        // numeric, but not decimal digits
+        assert_eq!(digit('½'), None);
+        assert_eq!(digit('Ⅻ'), None);
+        assert_eq!(digit('٩'), Some(9));
+    }
+
+    #[test]
+    fn sort() {
+        let mut xs: Vec<PathBuf> = ["img10.png", "IMG1.png", "img2.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        Sort::Natural.sort(&mut xs, true);
+        assert_eq!(
+            xs,
+            ["IMG1.png", "img2.png", "img10.png"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+        Sort::Name.sort(&mut xs, false);
+        assert_eq!(xs[0], PathBuf::from("IMG1.png"));
+        assert_eq!(xs[1], PathBuf::from("img10.png"));
+    }
+}
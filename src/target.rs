@@ -1,18 +1,24 @@
-#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Target {
     Dir,
     File,
-    /// TODO
+    /// Links themselves, never followed
     Symlink,
+    /// Files & folders, deepest first
+    All,
 }
 
-impl From<&str> for Target {
-    fn from(s: &str) -> Self {
-        match s {
+impl TryFrom<&str> for Target {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
             "File" | "file" => Self::File,
             "Directory" | "dir" | "Dir" | "Folder" => Self::Dir,
             "Symlink" | "link" => Self::Symlink,
-            _ => todo!(),
-        }
+            "All" | "all" | "Both" => Self::All,
+            _ => anyhow::bail!(crate::Error::BadInput(format!("Unknown target: {s}"))),
+        })
     }
 }
@@ -1,15 +1,19 @@
-#[derive(Debug, Clone, clap::ValueEnum)]
-pub enum Task {
-    Rename,
-    Undo,
-}
-
-impl From<&str> for Task {
-    fn from(s: &str) -> Self {
-        match s {
-            "Rename" => Self::Rename,
-            "Undo with history" => Self::Undo,
-            _ => todo!(),
-        }
-    }
-}
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Task {
+    Rename,
+    Undo,
+    Redo,
+}
+
+impl TryFrom<&str> for Task {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "Rename" => Self::Rename,
+            "Undo with history" => Self::Undo,
+            "Redo with history" => Self::Redo,
+            _ => anyhow::bail!(crate::Error::BadInput(format!("Unknown task: {s}"))),
+        })
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::Result;
+use chrono::format::{Item, StrftimeItems};
+use std::path::Path;
+
+use crate::{Error, BIT_MAX, INVALID_CHARS};
+
+const PLACEHOLDERS: &str = "{stem} {ext} {name} {parent} {n} {n:04} {random} {random:8} {uuid} {date} {date:%Y%m%d} {mtime} {mtime:%Y%m%d}";
+const FILTERS: &str = "lower upper title snake kebab camel";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone)]
+enum Field {
+    /// File stem
+    Stem,
+    /// Extension, without the dot
+    Ext,
+    /// File name
+    Name,
+    /// Name of the parent folder
+    Parent,
+    /// Counter, zero padded to an optional width
+    Num(Option<usize>),
+    /// Random ASCII letters and numbers, with an optional length
+    Random(Option<usize>),
+    Uuid,
+    /// Local time now
+    Date(String),
+    /// Modification time
+    Mtime(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Filter {
+    Lower,
+    Upper,
+    Title,
+    Snake,
+    Kebab,
+    Camel,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Field(Field, Vec<Filter>),
+}
+
+/// Values shared by files with the same stem
+#[derive(Debug, Clone)]
+pub(crate) struct Tokens {
+    pub n: usize,
+    pub random: String,
+    pub uuid: String,
+}
+
+/// Naming template, e.g. `{parent}_{n:04}_{stem|lower}.{ext}`
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl std::str::FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl Template {
+    /// Parse and validate a template: illegal characters, unknown placeholders & filters,
+    /// and widths above `BIT_MAX` are all reported at once.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut illegals = String::new();
+        let mut unknowns = Vec::new();
+        let mut wides = Vec::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => anyhow::bail!(Error::BadInput(format!("Unmatched `}}` in template: {s}"))),
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            None | Some('{') => anyhow::bail!(Error::BadInput(format!(
+                                "Unclosed `{{` in template: {s}"
+                            ))),
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                        }
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    match Self::parse_field(&spec) {
+                        Some(Segment::Field(Field::Num(Some(w)) | Field::Random(Some(w)), _))
+                            if w > BIT_MAX =>
+                        {
+                            wides.push(format!("{{{spec}}}"))
+                        }
+                        Some(segment) => segments.push(segment),
+                        None => unknowns.push(format!("{{{spec}}}")),
+                    }
+                }
+                c => {
+                    if INVALID_CHARS.contains(c) && !illegals.contains(c) {
+                        illegals.push(c);
+                    }
+                    text.push(c);
+                }
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        let mut errors = Vec::new();
+        if !illegals.is_empty() {
+            errors.push(format!("Illegal characters `{illegals}` in template: {s}. These are usually not allowed: {INVALID_CHARS}"));
+        }
+        if !unknowns.is_empty() {
+            errors.push(format!("Unknown placeholders: {}. Available: {PLACEHOLDERS}, with filters: {FILTERS}, e.g. {{stem|lower}}",
+                unknowns.join(", ")));
+        }
+        if !wides.is_empty() {
+            errors.push(format!(
+                "Widths too large: {}. It should be at most {BIT_MAX}.",
+                wides.join(", ")
+            ));
+        }
+        if !errors.is_empty() {
+            anyhow::bail!(Error::BadInput(errors.join("\n")));
+        }
+        if segments.is_empty() {
+            anyhow::bail!(Error::BadInput("Empty template".into()));
+        }
+        Ok(Self { segments })
+    }
+
+    fn parse_field(spec: &str) -> Option<Segment> {
+        let mut parts = spec.split('|');
+        let first = parts.next()?;
+        let (name, arg) = match first.split_once(':') {
+            None => (first, None),
+            Some((name, arg)) => (name, Some(arg)),
+        };
+        let field = match (name.trim(), arg) {
+            ("stem", None) => Field::Stem,
+            ("ext", None) => Field::Ext,
+            ("name", None) => Field::Name,
+            ("parent", None) => Field::Parent,
+            ("uuid", None) => Field::Uuid,
+            ("n", None) => Field::Num(None),
+            ("n", Some(x)) => Field::Num(Some(x.parse().ok()?)),
+            ("random", None) => Field::Random(None),
+            ("random", Some(x)) => Field::Random(Some(x.parse().ok().filter(|&x| x > 0)?)),
+            ("date", x) | ("mtime", x) => {
+                let fmt = x.unwrap_or(DATE_FORMAT).to_string();
+                if fmt.is_empty()
+                    || fmt.chars().any(|c| INVALID_CHARS.contains(c))
+                    || StrftimeItems::new(&fmt).any(|x| matches!(x, Item::Error))
+                {
+                    return None;
+                }
+                match name.trim() {
+                    "date" => Field::Date(fmt),
+                    _ => Field::Mtime(fmt),
+                }
+            }
+            _ => return None,
+        };
+        let mut filters = Vec::new();
+        for x in parts {
+            filters.push(match x.trim() {
+                "lower" => Filter::Lower,
+                "upper" => Filter::Upper,
+                "title" => Filter::Title,
+                "snake" => Filter::Snake,
+                "kebab" => Filter::Kebab,
+                "camel" => Filter::Camel,
+                _ => return None,
+            });
+        }
+        Some(Segment::Field(field, filters))
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &Field> {
+        self.segments.iter().filter_map(|x| match x {
+            Segment::Field(field, _) => Some(field),
+            Segment::Text(_) => None,
+        })
+    }
+
+    /// Whether `{n}` is used
+    pub fn uses_counter(&self) -> bool {
+        self.fields().any(|x| matches!(x, Field::Num(_)))
+    }
+
+    /// Whether rendering again can give another name
+    pub(crate) fn is_varying(&self) -> bool {
+        self.fields()
+            .any(|x| matches!(x, Field::Num(_) | Field::Random(_) | Field::Uuid))
+    }
+
+    /// The longest `{random}`, `nbits` by default
+    pub(crate) fn random_len(&self, nbits: usize) -> usize {
+        self.fields()
+            .filter_map(|x| match x {
+                Field::Random(n) => Some(n.unwrap_or(nbits)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Render the new file name of `p`. A dot right before an empty `{ext}` is dropped.
+    pub(crate) fn render(&self, p: &Path, tokens: &Tokens, nbits: usize) -> Result<String> {
+        let mut y = String::new();
+        for segment in self.segments.iter() {
+            let (field, filters) = match segment {
+                Segment::Text(x) => {
+                    y.push_str(x);
+                    continue;
+                }
+                Segment::Field(field, filters) => (field, filters),
+            };
+            let mut x = match field {
+                Field::Stem => Self::to_str(p.file_stem(), p)?.to_string(),
+                Field::Ext => {
+                    let ext = Self::to_str(p.extension(), p)?;
+                    if ext.is_empty() && y.ends_with('.') {
+                        y.pop();
+                    }
+                    ext.to_string()
+                }
+                Field::Name => Self::to_str(p.file_name(), p)?.to_string(),
+                Field::Parent => {
+                    Self::to_str(p.parent().and_then(|x| x.file_name()), p)?.to_string()
+                }
+                Field::Num(None) => tokens.n.to_string(),
+                Field::Num(Some(w)) => format!("{:0>w$}", tokens.n),
+                Field::Random(n) => tokens.random.chars().take(n.unwrap_or(nbits)).collect(),
+                Field::Uuid => tokens.uuid.clone(),
+                Field::Date(fmt) => Self::checked(chrono::Local::now().format(fmt).to_string())?,
+                Field::Mtime(fmt) => {
+                    let t = std::fs::symlink_metadata(p)?.modified()?;
+                    Self::checked(
+                        chrono::DateTime::<chrono::Local>::from(t)
+                            .format(fmt)
+                            .to_string(),
+                    )?
+                }
+            };
+            for filter in filters.iter() {
+                x = match filter {
+                    Filter::Lower => x.to_lowercase(),
+                    Filter::Upper => x.to_uppercase(),
+                    Filter::Title => crate::case::title(&x),
+                    Filter::Snake => crate::case::snake(&x),
+                    Filter::Kebab => crate::case::kebab(&x),
+                    Filter::Camel => crate::case::camel(&x),
+                };
+            }
+            y.push_str(&x);
+        }
+        if y.is_empty() || y == "." || y == ".." || y.contains(['/', '\\']) {
+            anyhow::bail!(Error::BadInput(format!(
+                "Invalid name rendered for {}: {:?}",
+                p.display(),
+                y
+            )));
+        }
+        Ok(y)
+    }
+
+    /// A date formatted, which `%D`, `%T`, `%c` & the like fill with `/` or `:`
+    fn checked(x: String) -> Result<String> {
+        match x.chars().find(|&c| INVALID_CHARS.contains(c) || c == '\\') {
+            None => Ok(x),
+            Some(c) => anyhow::bail!(Error::BadInput(format!(
+                "Illegal character `{c}` in date rendered: {x}. These are usually not allowed: {INVALID_CHARS}"
+            ))),
+        }
+    }
+
+    fn to_str<'a>(x: Option<&'a std::ffi::OsStr>, p: &Path) -> Result<&'a str> {
+        match x {
+            None => Ok(""),
+            Some(x) => match x.to_str() {
+                Some(x) => Ok(x),
+                None => anyhow::bail!(Error::BadInput(format!(
+                    "File name is not valid UTF-8: {}",
+                    p.display()
+                ))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, name: &str) -> String {
+        let tokens = Tokens {
+            n: 7,
+            random: "abcdefgh".to_string(),
+            uuid: String::new(),
+        };
+        Template::parse(template)
+            .unwrap()
+            .render(Path::new("/data/photos").join(name).as_path(), &tokens, 3)
+            .unwrap()
+    }
+
+    fn error(template: &str) -> String {
+        Template::parse(template).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            render("{parent}_{n:04}_{stem|lower}.{ext}", "My Img.JPG"),
+            "photos_0007_my img.JPG"
+        );
+        assert_eq!(render("{name|snake}", "My Img.jpg"), "my_img_jpg");
+        assert_eq!(render("{random}-{random:5}", "a.jpg"), "abc-abcde");
+        assert_eq!(render("{stem|kebab|upper}", "my_file.txt"), "MY-FILE");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render("{{{stem}}}", "a.jpg"), "{a}");
+        assert_eq!(render("{{n}}_{n}", "a.jpg"), "{n}_7");
+        assert!(error("{stem").contains("Unclosed"));
+        assert!(error("stem}").contains("Unmatched"));
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(render("{n}", "a.jpg"), "7");
+        // bare widths pad with zeros too
+        assert_eq!(render("{n:3}", "a.jpg"), "007");
+        assert_eq!(render("{n:03}", "a.jpg"), "007");
+        assert!(Template::parse("{n:20}").is_ok());
+        assert!(error("{n:21}").contains("{n:21}"));
+        assert!(error("{random:100000000000}").contains("at most"));
+        assert!(error("{random:0}").contains("Unknown"));
+    }
+
+    #[test]
+    fn empty_ext() {
+        // the dot before an empty `{ext}` is dropped
+        assert_eq!(render("{stem}_1.{ext}", "README"), "README_1");
+        assert_eq!(render("{stem}_1.{ext}", "a.tar"), "a_1.tar");
+        assert_eq!(render("{ext}", "a.tar"), "tar");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("{foo}").contains("{foo}"));
+        assert!(error("{stem|shout}").contains("{stem|shout}"));
+        assert!(error("{date:%Y/%m}").contains("Unknown"));
+        // all reported at once
+        let err = error("a<b>|{foo}_{bar}_{n:99}");
+        assert!(err.contains("`<>|`"));
+        assert!(err.contains("{foo}, {bar}"));
+        assert!(err.contains("{n:99}"));
+        assert!(Template::parse("").is_err());
+    }
+}